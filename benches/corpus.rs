@@ -0,0 +1,90 @@
+use std::collections::BTreeSet as Set;
+use std::path::PathBuf;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsearch::corpus::{Corpus, Keep};
+use tagsearch::filter::Filter;
+use tagsearch::metadata::{metadata_from_string, Metadata};
+use tagsearch::utility::*;
+use tagsearch::Tag;
+
+// See `tagparse.rs` for how the file was generated
+const MEDIUM_TAG_FILE: &str = include_str!("../medium-tag-file.md");
+
+/// Split the medium file into 100 notes of 10 lines each, on disk, so that
+/// reading files is part of what is measured
+///
+/// Every note has a `value` field and a `@journal` tag, like a vault where
+/// most files share a common tag, so a query for it matches every note.
+fn write_notes() -> Vec<String> {
+    let dir: PathBuf = std::env::temp_dir().join("tagsearch-bench-corpus");
+    std::fs::create_dir_all(&dir).expect("Couldn't create benchmark directory");
+    let lines: Vec<&str> = MEDIUM_TAG_FILE.lines().collect();
+    lines
+        .chunks(10)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let path = dir.join(format!("note{:03}.md", i));
+            let contents = format!("value: {}\n\n@journal\n{}", i, chunk.join("\n"));
+            std::fs::write(&path, contents).expect("Couldn't write benchmark note");
+            path.to_string_lossy().into_owned()
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let files = write_notes();
+    let keywords = ["journal"];
+    let none: [&str; 0] = [];
+    let f = Filter::new(&keywords, &none, false);
+
+    // `files --vim`: the matching files, then each wanted tag in them
+    let mut group = c.benchmark_group("Files with tag locations");
+    group.sample_size(20);
+    group.bench_function("Reading files for each step", |b| {
+        b.iter(|| {
+            f.files_matching_tag_query(&files)
+                .iter()
+                .flat_map(|fname| tag_occurrences(&read_contents(fname).unwrap()).collect::<Vec<_>>())
+                .filter(|o| f.wants_tag(&o.tag))
+                .count()
+        })
+    });
+    group.bench_function("Reading files once into a corpus", |b| {
+        b.iter(|| {
+            let mut corpus = Corpus::read(&files, Keep::Tags);
+            corpus.load_text(|doc| f.matches(&doc.tags));
+            f.documents_matching(&corpus)
+                .iter()
+                .flat_map(|doc| doc.occurrences().iter())
+                .filter(|o| f.wants_tag(&o.tag))
+                .count()
+        })
+    });
+    group.finish();
+
+    // `sum value --by tag`: the matching files, then their tags and metadata
+    let mut group = c.benchmark_group("Tags and metadata of matching files");
+    group.sample_size(20);
+    group.bench_function("Reading files for each step", |b| {
+        b.iter(|| {
+            f.files_matching_tag_query(&files)
+                .iter()
+                .map(|fname| (get_tags_for_file(fname).unwrap(), metadata_from_string(&read_contents(fname).unwrap())))
+                .collect::<Vec<(Set<Tag>, Metadata)>>()
+        })
+    });
+    group.bench_function("Reading files once into a corpus", |b| {
+        b.iter(|| {
+            let corpus = Corpus::read(&files, Keep::Metadata);
+            f.documents_matching(&corpus)
+                .iter()
+                .map(|doc| (doc.tags.clone(), doc.metadata.clone()))
+                .collect::<Vec<(Set<Tag>, Metadata)>>()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
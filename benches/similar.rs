@@ -0,0 +1,41 @@
+use std::collections::BTreeSet as Set;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tagsearch::filter::Filter;
+use tagsearch::utility::*;
+use tagsearch::Tag;
+
+// Real dictionary words give a realistic mix of plurals, near-misses, and
+// unrelated words (see `tagparse.rs` for how the file was generated)
+const MEDIUM_TAG_FILE: &str = include_str!("../medium-tag-file.md");
+
+/// The first `n` distinct tags from the medium file, cut to at most two
+/// levels so that tags share components like a real vault's would
+fn tags(n: usize) -> Set<Tag> {
+    let mut tags = Set::new();
+    for occurrence in tag_occurrences(MEDIUM_TAG_FILE) {
+        let mut tag = occurrence.tag;
+        tag.truncate(2);
+        tags.insert(tag);
+        if tags.len() == n {
+            break;
+        }
+    }
+    tags
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let f = Filter::default();
+    let mut group = c.benchmark_group("Similar tags");
+    group.sample_size(10);
+    for n in [1000, 8000].iter() {
+        let tagset = tags(*n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &tagset, |b, tagset| {
+            b.iter(|| f.similar_tags_in(tagset))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
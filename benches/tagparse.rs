@@ -4,16 +4,32 @@ use tagsearch::utility::*;
 // Tags generated randomly with python from /usr/share/dict/words
 // between 3 and 10 words per tag (all heirarchical)
 // MEDIUM -- 40 tags x 1000  lines
-// TALL   -- 20 tags x 10000 lines
+// TALL   -- 20 tags x 10000 lines (built from the medium file's tags)
 const MEDIUM_TAG_FILE: &str = include_str!("../medium-tag-file.md");
-const TALL_TAG_FILE: &str = include_str!("../tall-narrow-tag-file.md");
+
+fn tall_tag_file() -> String {
+    let tags: Vec<&str> = MEDIUM_TAG_FILE.split_whitespace().collect();
+    tags.chunks(20)
+        .cycle()
+        .take(10000)
+        .map(|line| line.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
 
 fn criterion_benchmark(c: &mut Criterion) {
+    let tall_tag_file = tall_tag_file();
     c.bench_function("Get tags from short, fat file", |b| {
         b.iter(|| get_tags_from_string(MEDIUM_TAG_FILE))
     });
     c.bench_function("Get tags from tall, skinny file", |b| {
-        b.iter(|| get_tags_from_string(TALL_TAG_FILE))
+        b.iter(|| get_tags_from_string(&tall_tag_file))
+    });
+    c.bench_function("Get tags from bytes of short, fat file", |b| {
+        b.iter(|| get_tags_from_bytes(MEDIUM_TAG_FILE.as_bytes()))
+    });
+    c.bench_function("Get tags from bytes of tall, skinny file", |b| {
+        b.iter(|| get_tags_from_bytes(tall_tag_file.as_bytes()))
     });
 }
 
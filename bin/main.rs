@@ -1,15 +1,65 @@
 use std::io::Write;
 
-use tagsearch::{filter::Filter, utility::*, Tag};
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
 
+use tagsearch::corpus::{Corpus, Document, Keep};
+use tagsearch::diff::{diff, snapshot};
+use tagsearch::grep::{build_pattern, grep_lines};
+use tagsearch::history::{format_unix_date, tag_history, Change};
+use tagsearch::lint::{Deprecated, LintFile, Linter, Severity, SimilarTags};
+use tagsearch::materialize::{apply_changes, is_view, plan_changes, view_links};
+use tagsearch::aggregate::{summarise, Aggregate, Grouping};
+use tagsearch::dates::{DateRange, DateSource};
+use tagsearch::metadata::{Date, Metadata};
+use tagsearch::query::Query;
+use tagsearch::registry::{ansi_colour, RegisteredTag, Registry, TagInfo};
+use tagsearch::sort::{Sort, SortKey, SORT_KEYS};
+use tagsearch::{config::Config, filter::Filter, utility::*, Tag};
+
+use rayon::prelude::*;
 use structopt::StructOpt;
 
+mod tui;
+
 #[derive(StructOpt,Debug)]
 struct Cli {
     #[structopt(subcommand)]
     command: Commands,
-    #[structopt(long)]
-    root: Option<String>,
+    /// Directory to search for files (can be repeated)
+    #[structopt(long, global = true, number_of_values = 1)]
+    root: Vec<String>,
+    /// Vault from the config file to search (can be repeated)
+    #[structopt(long, global = true, number_of_values = 1)]
+    vault: Vec<String>,
+    /// Group listed files by the vault or root they were found under
+    #[structopt(long, global = true)]
+    group_by_vault: bool,
+    /// Config file to use, instead of the default location
+    #[structopt(long, global = true)]
+    config: Option<String>,
+    /// Read file names from a file (or `-` for stdin) instead of searching
+    /// any root or vault, one per line or NUL-separated
+    #[structopt(long, global = true, conflicts_with_all = &["root", "vault"])]
+    files_from: Option<String>,
+    /// Only match files dated on or after this YYYY-MM-DD date
+    #[structopt(long, global = true)]
+    since: Option<Date>,
+    /// Only match files dated on or before this YYYY-MM-DD date
+    #[structopt(long, global = true)]
+    until: Option<Date>,
+    /// Only match files dated on this YYYY-MM-DD date
+    #[structopt(long, global = true, conflicts_with_all(&["since", "until"]))]
+    on: Option<Date>,
+    /// Where to find each file's date, in order: `filename`, `field`,
+    /// `field:NAME`, `content`, or `mtime` (default from the config)
+    #[structopt(long, global = true, require_delimiter(true))]
+    date_source: Vec<DateSource>,
+    /// Also search hidden files and directories
+    #[structopt(long, global = true)]
+    hidden: bool,
+    /// Don't skip files excluded by `.gitignore`, `.ignore`, and similar
+    #[structopt(long, global = true)]
+    no_ignore: bool,
 }
 
 #[derive(StructOpt,Debug)]
@@ -25,9 +75,39 @@ enum Commands {
         /// Output in format suitable for vimgrep
         #[structopt(long)]
         vim: bool,
+        /// Output in format suitable for Emacs compilation-mode or grep-mode
+        #[structopt(long, conflicts_with("vim"))]
+        emacs: bool,
+        /// Output NUL-separated `path<TAB>line<TAB>tags` records for fzf
+        #[structopt(long, conflicts_with_all(&["vim", "emacs"]))]
+        fzf: bool,
         /// Match ANY, not ALL, tags
         #[structopt(short, long)]
         or: bool,
+        /// Show which alias spellings of tags each file used
+        #[structopt(long, conflicts_with_all(&["vim", "emacs", "fzf"]))]
+        spellings: bool,
+        /// Metadata fields to show after each file, tab-separated
+        #[structopt(long, require_delimiter(true), conflicts_with_all(&["vim", "emacs", "fzf", "spellings"]))]
+        columns: Vec<String>,
+        /// Order to list files in
+        #[structopt(long, possible_values(&SORT_KEYS))]
+        sort: Option<SortKey>,
+        /// Reverse the order files are listed in
+        #[structopt(long, requires("sort"))]
+        reverse: bool,
+        /// Show at most this many files
+        #[structopt(long)]
+        limit: Option<usize>,
+        /// Only show files whose contents match this regular expression
+        #[structopt(long)]
+        grep: Option<String>,
+        /// Treat the --grep pattern as literal text, not a regular expression
+        #[structopt(short = "F", long, requires("grep"))]
+        fixed_strings: bool,
+        /// Show lines matching --grep, with this many lines either side
+        #[structopt(long, requires("grep"), conflicts_with_all(&["vim", "emacs", "fzf", "spellings", "columns"]))]
+        context: Option<usize>,
     },
     /// Show all tags from files with tags that match filter words
     #[structopt(aliases=&["t"])]
@@ -49,6 +129,9 @@ enum Commands {
         /// Stop 'tree' output in long list
         #[structopt(short, long)]
         no_tree: bool,
+        /// Output as JSON, with what the registry says about each tag
+        #[structopt(long, conflicts_with("count"))]
+        json: bool,
     },
     /// Show tags from specific files
     #[structopt(aliases=&["ft"])]
@@ -62,7 +145,7 @@ enum Commands {
         /// Stop 'tree' output in long list
         #[structopt(short, long)]
         no_tree: bool,
-        /// Files to extract tags from
+        /// Files to extract tags from (`-` to read from stdin)
         files: Vec<String>,
     },
     /// Show files without tags
@@ -75,22 +158,327 @@ enum Commands {
     /// Show tags that may be typos/slight differences
     #[structopt(aliases=&["similar", "related", "s"])]
     SimilarTags,
+    /// Interactively browse tags and the files using them
+    Tui,
+    /// Show the tagged lines of a file, with tags highlighted (for fzf --preview)
+    Preview {
+        /// File to preview, optionally as FILE:LINE to centre on a line
+        target: String,
+        /// Lines of context to show around each tagged line
+        #[structopt(short = "C", long, default_value = "3")]
+        context: usize,
+        /// Don't highlight tags with colour
+        #[structopt(long)]
+        no_color: bool,
+    },
+    /// Show when a tag was introduced, and how its use changed, in git history
+    History {
+        /// Tag to trace
+        tag: String,
+        /// Any directory inside the git repository (the whole repository is searched)
+        #[structopt(long, default_value = ".")]
+        repo: String,
+        /// Revision to trace history back from
+        #[structopt(long, default_value = "HEAD")]
+        rev: String,
+    },
+    /// Compare tags between two directories or git revisions
+    Diff {
+        /// Directory or git revision to compare from
+        before: String,
+        /// Directory or git revision to compare to
+        after: String,
+        /// Directory inside the git repository, when comparing revisions
+        #[structopt(long, default_value = ".")]
+        repo: String,
+        /// Output as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Sum a numeric metadata field across files matching filter words
+    Sum(AggregateArgs),
+    /// Average a numeric metadata field across files matching filter words
+    Avg(AggregateArgs),
+    /// Smallest value of a numeric metadata field in files matching filter words
+    Min(AggregateArgs),
+    /// Largest value of a numeric metadata field in files matching filter words
+    Max(AggregateArgs),
+    /// Count files matching filter words that have a numeric metadata field
+    Count(AggregateArgs),
+    /// Show what the tag registry says about a tag, and how often it is used
+    Describe {
+        /// Tag to describe
+        tag: String,
+    },
+    /// Show why a file does or doesn't match filter words
+    Explain {
+        /// Keywords to match, then the file to check (`-` to read from stdin)
+        #[structopt(required = true, value_name = "KEYWORDS... FILE")]
+        args: Vec<String>,
+        #[structopt(long, require_delimiter(true))]
+        /// Keywords to NOT match
+        not: Vec<String>,
+        /// Match ANY, not ALL, tags
+        #[structopt(short, long)]
+        or: bool,
+    },
+    /// Show files matching a query saved in the config
+    ///
+    /// A saved query's name can also be used as a keyword in other queries,
+    /// e.g. `tagsearch files reading --sort date`.
+    Run {
+        /// Name of the saved query
+        name: String,
+    },
+    /// List the queries saved in the config
+    Queries,
+    /// Make a directory for each tag, of links to the files using it
+    ///
+    /// Running it again updates the links to match the files' tags, and
+    /// removes links for tags that files no longer have.
+    Materialize {
+        /// Directory to make the links in
+        outdir: String,
+        /// Keywords to match, to only link some files
+        good: Vec<String>,
+        #[structopt(long, require_delimiter(true))]
+        /// Keywords to NOT match
+        not: Vec<String>,
+        /// Match ANY, not ALL, tags
+        #[structopt(short, long)]
+        or: bool,
+        /// Show what would change, without changing anything
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    /// Check tags against the lint rules in the config, failing on errors
+    Lint {
+        /// Output as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+}
+
+/// Arguments shared by every command that aggregates a metadata field
+#[derive(StructOpt, Debug)]
+struct AggregateArgs {
+    /// Numeric metadata field to aggregate, e.g. `value`
+    field: String,
+    /// Keywords to match
+    good: Vec<String>,
+    #[structopt(long, require_delimiter(true))]
+    /// Keywords to NOT match
+    not: Vec<String>,
+    /// Match ANY, not ALL, tags
+    #[structopt(short, long)]
+    or: bool,
+    /// Aggregate separately for each `tag`, or each `month` (of the `date`
+    /// field, or of FIELD with `month:FIELD`)
+    #[structopt(long)]
+    by: Option<Grouping>,
+}
+
+/// How to print files that match a query
+enum FileFormat {
+    Plain,
+    Vim,
+    Emacs,
+    Fzf,
+    /// Plain, followed by any aliases used and their canonical tags
+    Spellings,
+    /// Plain, followed by the values of metadata fields
+    Columns(Vec<String>),
+    /// Lines matching the filter's pattern, with this many lines of context
+    Context(usize),
+}
+
+impl FileFormat {
+    /// How much of each file showing it needs
+    fn keep(&self) -> Keep {
+        match self {
+            FileFormat::Vim | FileFormat::Emacs | FileFormat::Fzf | FileFormat::Context(_) => Keep::Text,
+            FileFormat::Columns(_) => Keep::Metadata,
+            _ => Keep::Tags,
+        }
+    }
+}
+
+/// A single tag within a file that satisfied one of the query keywords
+struct Match {
+    line: usize,
+    byte_column: usize,
+    char_column: usize,
+    tag: Tag,
+    text: String,
+}
+
+/// How to walk directories for files, from the global flags
+///
+/// Tag views made by `materialize` are always skipped, as they only link to
+/// files found elsewhere.
+fn walk_options(cli: &Cli) -> WalkOptions {
+    WalkOptions {
+        hidden: cli.hidden,
+        no_ignore: cli.no_ignore,
+        skip_dir: Some(is_view),
+    }
+}
+
+/// Find files under every requested root and vault
+///
+/// Returns the files, along with the name of the vault (or the root, if it
+/// was given with `--root`) that each file was found under. A file found
+/// under several roots is only listed for the first.
+fn files_in_vaults(cli: &Cli, config: &Config) -> Result<Vec<(String, String)>, std::io::Error> {
+    let mut sources: Vec<(String, String)> = Vec::new();
+    for name in &cli.vault {
+        sources.push((name.to_string(), config.vault_root(name)?));
+    }
+    for root in &cli.root {
+        sources.push((root.to_string(), root.to_string()));
+    }
+    if sources.is_empty() {
+        sources.push((".".to_string(), ".".to_string()));
+    }
+
+    let options = walk_options(cli);
+    let mut seen = Set::new();
+    let mut files = Vec::new();
+    for (name, root) in sources {
+        let found = find_files(&root, options)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("Error getting files: {}", e)))?;
+        for fname in found {
+            let key = std::fs::canonicalize(&fname).unwrap_or_else(|_| fname.clone().into());
+            if seen.insert(key) {
+                files.push((name.to_string(), fname));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The first root being searched, which is where `tags.toml` is looked for
+fn search_root(cli: &Cli, config: &Config) -> Result<String, std::io::Error> {
+    match (cli.vault.first(), cli.root.first()) {
+        (Some(vault), _) => config.vault_root(vault),
+        (None, Some(root)) => Ok(root.to_string()),
+        (None, None) => Ok(".".to_string()),
+    }
+}
+
+/// Load the registry named in the config, or else `tags.toml` in `root`
+fn load_registry(root: &str, config: &Config) -> Result<Registry, std::io::Error> {
+    match config.registry_path() {
+        Some(path) => Registry::load(std::path::Path::new(&path)),
+        None => Registry::find(root),
+    }
+}
+
+/// Build the filter for a query, using the aliases and saved queries in the
+/// config
+fn query_filter<'a>(
+    good: &'a [String],
+    not: &'a [String],
+    or: bool,
+    config: &Config,
+    queries: &'a Map<String, Query>,
+    dates: DateRange,
+    date_sources: &[DateSource],
+) -> Filter<'a> {
+    Filter::new(good, not, or)
+        .with_aliases(&config.aliases)
+        .with_queries(queries)
+        .with_dates(dates, date_sources)
 }
 
 fn try_main() -> Result<(), std::io::Error> {
     let cli = Cli::from_args();
-    let files = match get_files(cli.root) {
-        Ok(files) => files,
-        Err(e) => {
-            println!("Error getting files: {}", e);
-            std::process::exit(1)
+    let config = Config::load(cli.config.as_deref())?;
+    let queries = config.saved_queries()?;
+    let (files, file_vaults): (Vec<String>, Option<Map<String, String>>) = match &cli.files_from {
+        Some(source) if source == STDIN_FILENAME => (read_file_list(std::io::stdin())?, None),
+        Some(source) => (read_file_list(std::fs::File::open(source)?)?, None),
+        None => {
+            let found = files_in_vaults(&cli, &config)?;
+            let vaults = found
+                .iter()
+                .map(|(vault, fname)| (fname.to_string(), vault.to_string()))
+                .collect();
+            let files = found.into_iter().map(|(_, fname)| fname).collect();
+            (files, Some(vaults))
         }
     };
+    // Only group output when asked to, and when we know where files came from
+    let vaults = file_vaults.as_ref().filter(|_| cli.group_by_vault);
+    let root = search_root(&cli, &config)?;
+    let dates = match cli.on {
+        Some(date) => DateRange::on(date),
+        None => DateRange {
+            since: cli.since,
+            until: cli.until,
+        },
+    };
+    let date_sources = if cli.date_source.is_empty() {
+        config.date_sources()
+    } else {
+        cli.date_source.clone()
+    };
+    let walk_options = walk_options(&cli);
+    // Every file is read once, keeping only what the command needs, and
+    // shared by whatever it shows
+    let corpus = |keep| read_corpus(&files, keep);
+    let matching = |f: &Filter, keep| read_matching(&files, f, keep);
 
     match cli.command {
-        Commands::Files { good, not, vim, or } => {
-            let f = Filter::new(good.as_slice(), not.as_slice(), or);
-            display_files_matching_query(f, &files, vim)
+        Commands::Files {
+            good,
+            not,
+            vim,
+            emacs,
+            fzf,
+            or,
+            spellings,
+            columns,
+            sort,
+            reverse,
+            limit,
+            grep,
+            fixed_strings,
+            context,
+        } => {
+            let mut f = query_filter(&good, &not, or, &config, &queries, dates, &date_sources);
+            if let Some(pattern) = grep {
+                f = f.with_pattern(build_pattern(&pattern, fixed_strings)?);
+            }
+            let format = if vim {
+                FileFormat::Vim
+            } else if emacs {
+                FileFormat::Emacs
+            } else if fzf {
+                FileFormat::Fzf
+            } else if spellings {
+                FileFormat::Spellings
+            } else if !columns.is_empty() {
+                FileFormat::Columns(columns)
+            } else if let Some(context) = context {
+                FileFormat::Context(context)
+            } else {
+                FileFormat::Plain
+            };
+            // Files are listed by path anyway, so --limit alone keeps that order
+            let sort = if sort.is_some() || limit.is_some() {
+                Some(Sort {
+                    key: sort.unwrap_or(SortKey::Path),
+                    reverse,
+                    limit,
+                    date_sources: date_sources.clone(),
+                })
+            } else {
+                None
+            };
+            let keep = f.keep().max(format.keep()).max(sort.as_ref().map_or(Keep::Tags, Sort::keep));
+            let corpus = matching(&f, keep);
+            display_files_matching_query(f, &corpus, format, vaults, sort.as_ref())
         }
         Commands::Tags {
             good,
@@ -99,30 +487,151 @@ fn try_main() -> Result<(), std::io::Error> {
             count,
             long,
             no_tree,
+            json,
         } => {
-            let f = Filter::new(good.as_slice(), not.as_slice(), or);
+            let f = query_filter(&good, &not, or, &config, &queries, dates, &date_sources);
+            let registry = load_registry(&root, &config)?;
+            let corpus = matching(&f, f.keep());
             if count {
-                display_tag_count(f, &files)
+                display_tag_count(f, &corpus)
+            } else if json {
+                display_tags_json(f, &corpus, &registry)
             } else {
-                display_tags(f, &files, long, no_tree)
+                display_tags(f, &corpus, long, no_tree, &registry)
             }
         }
         Commands::FileTags {
             count,
             long,
             no_tree,
-            files,
+            files: named_files,
         } => {
-            let f: Filter = Default::default();
+            if cli.files_from.as_deref() == Some(STDIN_FILENAME) && named_files.iter().any(|f| f == STDIN_FILENAME) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "Can't read both the file list (`--files-from -`) and a file (`-`) from stdin",
+                ));
+            }
+            // Explicitly named files are used along with any `--files-from` list
+            let files = match cli.files_from {
+                Some(_) => named_files.into_iter().chain(files).collect(),
+                None => named_files,
+            };
+            let f = Filter::default().with_aliases(&config.aliases);
+            let registry = load_registry(&root, &config)?;
+            let corpus = read_corpus(&files, Keep::Tags);
             if count {
-                display_tag_count(f, &files)
+                display_tag_count(f, &corpus)
             } else {
-                display_tags(f, &files, long, no_tree)
+                display_tags(f, &corpus, long, no_tree, &registry)
+            }
+        }
+        Commands::Untagged { vim } => display_untagged(&corpus(Keep::Tags), vim, vaults),
+        Commands::SimilarTags => display_similar_tags(&corpus(Keep::Tags), &config),
+        Commands::Tui => tui::run(corpus(Keep::Tags)),
+        Commands::Preview {
+            target,
+            context,
+            no_color,
+        } => display_preview(&target, context, !no_color),
+        Commands::History { tag, repo, rev } => display_history(&tag, &repo, &rev),
+        Commands::Diff {
+            before,
+            after,
+            repo,
+            json,
+        } => display_diff(&before, &after, &repo, walk_options, json),
+        Commands::Sum(args) => {
+            let f = query_filter(&args.good, &args.not, args.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep().max(Keep::Metadata));
+            display_aggregate(Aggregate::Sum, f, &args, &corpus)
+        }
+        Commands::Avg(args) => {
+            let f = query_filter(&args.good, &args.not, args.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep().max(Keep::Metadata));
+            display_aggregate(Aggregate::Avg, f, &args, &corpus)
+        }
+        Commands::Min(args) => {
+            let f = query_filter(&args.good, &args.not, args.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep().max(Keep::Metadata));
+            display_aggregate(Aggregate::Min, f, &args, &corpus)
+        }
+        Commands::Max(args) => {
+            let f = query_filter(&args.good, &args.not, args.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep().max(Keep::Metadata));
+            display_aggregate(Aggregate::Max, f, &args, &corpus)
+        }
+        Commands::Count(args) => {
+            let f = query_filter(&args.good, &args.not, args.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep().max(Keep::Metadata));
+            display_aggregate(Aggregate::Count, f, &args, &corpus)
+        }
+        Commands::Describe { tag } => {
+            let registry = load_registry(&root, &config)?;
+            display_description(&tag, &corpus(Keep::Tags), &registry, &config)
+        }
+        Commands::Explain { args, not, or } => {
+            // `args` is required, so always has the file last
+            let (file, good) = args.split_last().unwrap();
+            let f = query_filter(good, &not, or, &config, &queries, dates, &date_sources);
+            display_explanation(&f, &Document::read(file, f.keep())?, or)
+        }
+        Commands::Run { name } => {
+            let query = queries.get(&name).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("No saved query named `{}` in config", name),
+                )
+            })?;
+            let f = query_filter(&query.good, &query.bad, query.or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep());
+            display_files_matching_query(f, &corpus, FileFormat::Plain, vaults, None)
+        }
+        Commands::Queries => {
+            let mut stdout = std::io::stdout();
+            for (name, query) in &config.queries {
+                writeln!(&mut stdout, "{:16} {}", name, query)?;
             }
+            Ok(())
+        }
+        Commands::Materialize {
+            outdir,
+            good,
+            not,
+            or,
+            dry_run,
+        } => {
+            let f = query_filter(&good, &not, or, &config, &queries, dates, &date_sources);
+            let corpus = matching(&f, f.keep());
+            display_materialize(&f, &corpus, &outdir, dry_run)
         }
-        Commands::Untagged { vim } => display_untagged(&files, vim),
-        Commands::SimilarTags => display_similar_tags(&files),
+        Commands::Lint { json } => display_lint(&files, file_vaults.as_ref(), &config, json),
+    }
+}
+
+/// Read every file, reporting any that couldn't be read
+fn read_corpus(files: &[String], keep: Keep) -> Corpus {
+    let corpus = Corpus::read(files, keep);
+    for e in &corpus.unreadable {
+        eprintln!("{}", e);
+    }
+    corpus
+}
+
+/// Read every file, reporting any that couldn't be read
+///
+/// Files are first read for their tags (and metadata), and text is only kept
+/// for those that match `f` on them, as no other file's text is searched or
+/// shown.
+fn read_matching(files: &[String], f: &Filter, keep: Keep) -> Corpus {
+    let mut corpus = Corpus::read(files, keep.min(Keep::Metadata));
+    if keep == Keep::Text {
+        corpus.load_text(|doc| f.matches_with_metadata(&doc.tags, &doc.metadata));
+    }
+    for e in &corpus.unreadable {
+        eprintln!("{}", e);
     }
+    corpus
 }
 
 fn main() {
@@ -134,76 +643,323 @@ fn main() {
     }
 }
 
-fn display_untagged(files: &[String], vim_format: bool) -> Result<(), std::io::Error> {
-    let f = Filter::default();
-    for fname in f.untagged_files(files) {
-        if vim_format {
-            writeln!(&mut std::io::stdout(), "{}:1:NO TAGS", fname)?;
-        } else {
-            writeln!(&mut std::io::stdout(), "{}", fname)?;
+/// Write one file per line, under a heading for each vault if `vaults` is given
+fn write_file_list(
+    files: &[String],
+    vaults: Option<&Map<String, String>>,
+) -> Result<(), std::io::Error> {
+    let mut stdout = std::io::stdout();
+    let vaults = match vaults {
+        Some(vaults) => vaults,
+        None => return writeln!(&mut stdout, "{}", files.join("\n")),
+    };
+    // Files may have been sorted, so gather each vault's files together,
+    // keeping them in order within it
+    let mut groups: Vec<(Option<&str>, Vec<&str>)> = Vec::new();
+    for fname in files {
+        let vault = vaults.get(fname).map(|v| v.as_str());
+        match groups.iter_mut().find(|(v, _)| *v == vault) {
+            Some((_, group)) => group.push(fname),
+            None => groups.push((vault, vec![fname])),
+        }
+    }
+    for (i, (vault, group)) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(&mut stdout)?;
+        }
+        writeln!(&mut stdout, "{}:", vault.unwrap_or("-"))?;
+        for fname in group {
+            writeln!(&mut stdout, "{}", fname)?;
         }
     }
     Ok(())
 }
 
-fn display_similar_tags(files: &[String]) -> Result<(), std::io::Error> {
-    let f = Filter::default();
-    let similar = f.similar_tags(files);
+fn display_untagged(
+    corpus: &Corpus,
+    vim_format: bool,
+    vaults: Option<&Map<String, String>>,
+) -> Result<(), std::io::Error> {
+    let untagged: Vec<String> = corpus
+        .documents
+        .iter()
+        .filter(|doc| doc.tags.is_empty())
+        .map(|doc| doc.path.to_string())
+        .collect();
+    if vim_format {
+        for fname in untagged {
+            writeln!(&mut std::io::stdout(), "{}:1:NO TAGS", fname)?;
+        }
+        Ok(())
+    } else if untagged.is_empty() {
+        Ok(())
+    } else {
+        write_file_list(&untagged, vaults)
+    }
+}
+
+fn display_similar_tags(corpus: &Corpus, config: &Config) -> Result<(), std::io::Error> {
+    let f = Filter::default()
+        .with_synonyms(&config.similar.synonyms)
+        .with_aliases(&config.aliases);
+    let mut similar = f.similar_tags_in(&corpus.tags());
+    similar.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
     if !similar.is_empty() {
         writeln!(&mut std::io::stdout(), "Similar tags:")?;
         for issue in similar {
-            writeln!(&mut std::io::stdout(), "{}", issue)?;
+            writeln!(&mut std::io::stdout(), "{:.2} {}", issue.confidence(), issue)?;
         }
     }
     Ok(())
 }
 
+/// Every occurrence of a tag wanted by the filter, in a file that matches it
+fn matches_in_file(f: &Filter, doc: &Document) -> Vec<Match> {
+    let lines: Vec<&str> = doc.contents().lines().collect();
+    let mut matches = Vec::new();
+    for occurrence in doc.occurrences() {
+        if !f.wants_tag(&occurrence.tag) {
+            continue;
+        }
+        let line = lines[occurrence.line - 1];
+        let byte_column = line
+            .char_indices()
+            .nth(occurrence.column - 1)
+            .map_or(0, |(i, _)| i);
+        matches.push(Match {
+            line: occurrence.line,
+            byte_column: byte_column + 1,
+            char_column: occurrence.column,
+            tag: occurrence.tag.clone(),
+            text: line.to_string(),
+        });
+    }
+    matches
+}
+
+/// Placeholder for a file that matched, but where no single tag can be
+/// pointed at (e.g. a query with only `--not` keywords)
+fn file_level_match(doc: &Document) -> Match {
+    let tags: Vec<String> = doc
+        .tags
+        .iter()
+        .map(|t| t.join("/"))
+        .collect();
+    Match {
+        line: 1,
+        byte_column: 1,
+        char_column: 1,
+        tag: Tag::new(),
+        text: format!("file tags: {}", tags.join(" ")),
+    }
+}
+
 fn display_files_matching_query(
     f: Filter,
-    files: &[String],
-    vim_format: bool,
+    corpus: &Corpus,
+    format: FileFormat,
+    vaults: Option<&Map<String, String>>,
+    sort: Option<&Sort>,
 ) -> Result<(), std::io::Error> {
-    if vim_format {
-        let mut vimstrings: Vec<String> = Vec::new();
-        for filename in f.files_matching_tag_query(files) {
-            let contents = std::fs::read_to_string(filename.clone())?;
-            for (i, line) in contents.lines().enumerate() {
-                let tags_in_line = get_tags_from_string(line);
-                if tags_in_line.is_empty() {
-                    continue;
+    let mut stdout = std::io::stdout();
+    let mut matching = f.documents_matching(corpus);
+    if let Some(sort) = sort {
+        matching = sort.apply(matching, &f, corpus);
+    }
+    if let FileFormat::Plain = format {
+        let paths: Vec<String> = matching.iter().map(|doc| doc.path.to_string()).collect();
+        return write_file_list(&paths, vaults);
+    }
+    if let FileFormat::Spellings = format {
+        for doc in matching {
+            let filename = &doc.path;
+            let used: Vec<String> = f
+                .aliases_used(&doc.tags)
+                .iter()
+                .map(|(alias, canonical)| format!("{} -> {}", alias.join("/"), canonical.join("/")))
+                .collect();
+            if used.is_empty() {
+                writeln!(&mut stdout, "{}", filename)?;
+            } else {
+                writeln!(&mut stdout, "{}\t{}", filename, used.join(", "))?;
+            }
+        }
+        return Ok(());
+    }
+    if let FileFormat::Columns(columns) = &format {
+        for doc in matching {
+            let values: Vec<String> = columns
+                .iter()
+                .map(|key| match doc.metadata.get(&key.to_lowercase()) {
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                })
+                .collect();
+            writeln!(&mut stdout, "{}\t{}", doc.path, values.join("\t"))?;
+        }
+        return Ok(());
+    }
+    if let (FileFormat::Context(context), Some(pattern)) = (&format, f.pattern()) {
+        // Like grep, `:` marks matching lines and `-` context lines, with
+        // `--` between groups of lines
+        let mut first = true;
+        for doc in matching {
+            for group in grep_lines(doc.contents(), pattern, *context) {
+                if !first && *context > 0 {
+                    writeln!(&mut stdout, "--")?;
                 }
-                if f.matches(&tags_in_line) {
-                    vimstrings.push(format!("{}:{}:1:{}", filename, i + 1, line));
+                first = false;
+                for line in group {
+                    let sep = if line.is_match { ':' } else { '-' };
+                    writeln!(&mut stdout, "{}{}{}{}{}", doc.path, sep, line.line, sep, line.text)?;
                 }
             }
         }
-        writeln!(&mut std::io::stdout(), "{}", vimstrings.join("\n"))?;
-    } else {
-        writeln!(
-            &mut std::io::stdout(),
-            "{}",
-            f.files_matching_tag_query(files).join("\n")
-        )?;
+        return Ok(());
+    }
+    for doc in matching {
+        let filename = &doc.path;
+        let mut matches = matches_in_file(&f, doc);
+        if matches.is_empty() {
+            matches.push(file_level_match(doc));
+        }
+        match format {
+            FileFormat::Vim => {
+                for m in matches {
+                    writeln!(&mut stdout, "{}:{}:{}:{}", filename, m.line, m.byte_column, m.text)?;
+                }
+            }
+            FileFormat::Emacs => {
+                for m in matches {
+                    writeln!(&mut stdout, "{}:{}:{}: {}", filename, m.line, m.char_column, m.text)?;
+                }
+            }
+            FileFormat::Fzf => {
+                // One record per line, listing every wanted tag on that line
+                let mut lines: Vec<(usize, Vec<String>)> = Vec::new();
+                for m in matches {
+                    match lines.last_mut() {
+                        Some((line, tags)) if *line == m.line => tags.push(m.tag.join("/")),
+                        _ => lines.push((m.line, vec![m.tag.join("/")])),
+                    }
+                }
+                for (line, tags) in lines {
+                    write!(&mut stdout, "{}\t{}\t{}\0", filename, line, tags.join(" "))?;
+                }
+            }
+            FileFormat::Plain | FileFormat::Spellings | FileFormat::Columns(_) | FileFormat::Context(_) => {
+                unreachable!()
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Split `FILE:LINE` into its parts, treating the whole string as the file
+/// name if there is no numeric suffix
+fn parse_file_and_line(target: &str) -> (&str, Option<usize>) {
+    match target.rsplit_once(':') {
+        Some((fname, line)) => match line.parse() {
+            Ok(line) => (fname, Some(line)),
+            Err(_) => (target, None),
+        },
+        None => (target, None),
+    }
+}
+
+/// Wrap every tag in a line in ANSI bold yellow
+fn highlight_tags(line: &str) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for occurrence in tag_occurrences(line) {
+        let range = occurrence.byte_range;
+        out.push_str(&line[last..range.start]);
+        out.push_str(&format!("\x1b[1;33m{}\x1b[0m", &line[range.clone()]));
+        last = range.end;
+    }
+    out.push_str(&line[last..]);
+    out
+}
+
+fn display_preview(target: &str, context: usize, color: bool) -> Result<(), std::io::Error> {
+    let (filename, target_line) = parse_file_and_line(target);
+    let contents = read_contents(filename)?;
+    let lines: Vec<&str> = contents.lines().collect();
+    let centres: Vec<usize> = match target_line {
+        Some(line) => vec![line.saturating_sub(1)],
+        None => {
+            let mut centres: Vec<usize> =
+                tag_occurrences(&contents).map(|o| o.line - 1).collect();
+            centres.dedup();
+            centres
+        }
+    };
+    // Merge overlapping windows around each centre line, as grep does
+    let mut windows: Vec<(usize, usize)> = Vec::new();
+    for centre in centres {
+        let start = centre.saturating_sub(context);
+        let end = (centre + context + 1).min(lines.len());
+        match windows.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => windows.push((start, end)),
+        }
+    }
+    let width = lines.len().to_string().len();
+    let mut stdout = std::io::stdout();
+    for (n, (start, end)) in windows.into_iter().enumerate() {
+        if n > 0 {
+            writeln!(&mut stdout, "--")?;
+        }
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            let marker = if Some(i + 1) == target_line { '>' } else { ' ' };
+            let line = if color {
+                highlight_tags(line)
+            } else {
+                line.to_string()
+            };
+            writeln!(&mut stdout, "{}{:>width$} {}", marker, i + 1, line, width = width)?;
+        }
     }
     Ok(())
 }
 
+/// A short note on what the registry says about a tag, for long listings
+fn registry_note(info: &TagInfo) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(description) = &info.description {
+        parts.push(description.to_string());
+    }
+    if let Some(replacement) = &info.deprecated_for {
+        parts.push(format!("(deprecated, use {})", replacement));
+    }
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" "))
+    }
+}
+
 fn display_tags(
     f: Filter,
-    files: &[String],
+    corpus: &Corpus,
     long_list: bool,
     no_tree: bool,
+    registry: &Registry,
 ) -> Result<(), std::io::Error> {
     // Convert the Btreeset into a vec
-    let tags: Vec<Tag> = f.tags_matching_tag_query(files).iter().cloned().collect();
+    let tags: Vec<Tag> = f.tags_matching_in(corpus).iter().cloned().collect();
+    let note = |tag: &[String]| registry.get(tag).and_then(registry_note);
 
     if long_list {
         if !no_tree {
-            writeln!(&mut std::io::stdout(), "{}", display_as_tree(&tags))?;
+            writeln!(&mut std::io::stdout(), "{}", display_as_annotated_tree(&tags, note))?;
         } else {
             let tags = tags
                 .iter()
-                .map(|tags| tags.join("/"))
+                .map(|tag| match note(tag) {
+                    Some(note) => format!("{}  {}", tag.join("/"), note),
+                    None => tag.join("/"),
+                })
                 .collect::<Vec<String>>()
                 .join("\n");
             writeln!(&mut std::io::stdout(), "{}", tags)?;
@@ -219,9 +975,366 @@ fn display_tags(
     Ok(())
 }
 
-fn display_tag_count(f: Filter, files: &[String]) -> Result<(), std::io::Error> {
-    for (count, key) in f.count_of_tags(files) {
+fn display_tags_json(f: Filter, corpus: &Corpus, registry: &Registry) -> Result<(), std::io::Error> {
+    let tags: Vec<RegisteredTag> = f
+        .tags_matching_in(corpus)
+        .iter()
+        .map(|tag| RegisteredTag {
+            tag: tag.join("/"),
+            info: registry.get(tag),
+        })
+        .collect();
+    let mut stdout = std::io::stdout();
+    serde_json::to_writer_pretty(&mut stdout, &tags)?;
+    writeln!(&mut stdout)
+}
+
+/// Format a number without trailing zeros, to at most 2 decimal places
+fn format_number(n: f64) -> String {
+    let s = format!("{:.2}", n);
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+fn display_aggregate(
+    aggregate: Aggregate,
+    f: Filter,
+    args: &AggregateArgs,
+    corpus: &Corpus,
+) -> Result<(), std::io::Error> {
+    let matching: Vec<(Set<Tag>, &Metadata)> = f
+        .documents_matching(corpus)
+        .into_iter()
+        .map(|doc| (f.canonical_tags(&doc.tags).into_owned(), &doc.metadata))
+        .collect();
+    let grouping = args.by.clone().unwrap_or(Grouping::All);
+    let groups = summarise(matching.iter().map(|(t, m)| (t, *m)), &args.field, &grouping);
+
+    let mut stdout = std::io::stdout();
+    if grouping == Grouping::All {
+        let total = groups.get("total").copied().unwrap_or_default();
+        return match total.get(aggregate) {
+            Some(n) => writeln!(&mut stdout, "{}", format_number(n)),
+            None => Ok(()),
+        };
+    }
+    for (group, summary) in groups {
+        if let Some(n) = summary.get(aggregate) {
+            writeln!(&mut stdout, "{}\t{}", group, format_number(n))?;
+        }
+    }
+    Ok(())
+}
+
+fn display_description(
+    tag: &str,
+    corpus: &Corpus,
+    registry: &Registry,
+    config: &Config,
+) -> Result<(), std::io::Error> {
+    let f = Filter::default().with_aliases(&config.aliases);
+    let tag = f.canonical_tag(&parse_heirarchical_tag(tag));
+    let used_in = corpus
+        .documents
+        .iter()
+        .filter(|doc| {
+            f.canonical_tags(&doc.tags)
+                .iter()
+                .any(|t| tag_is_within(t, &tag))
+        })
+        .count();
+
+    let mut stdout = std::io::stdout();
+    let (name, info) = match registry.get_entry(&tag) {
+        Some((name, info)) => (name.to_string(), info.clone()),
+        None => (tag.join("/"), TagInfo::default()),
+    };
+    let colour = info
+        .colour
+        .as_deref()
+        .and_then(ansi_colour)
+        .filter(|_| std::io::IsTerminal::is_terminal(&stdout));
+    match colour {
+        Some(code) => writeln!(&mut stdout, "{}{}\x1b[0m", code, name)?,
+        None => writeln!(&mut stdout, "{}", name)?,
+    }
+    if registry.get(&tag).is_none() {
+        writeln!(&mut stdout, "    Not in the tag registry")?;
+    }
+    let fields = [
+        ("Description", &info.description),
+        ("Owner", &info.owner),
+        ("Colour", &info.colour),
+        ("Deprecated for", &info.deprecated_for),
+    ];
+    for (label, value) in fields.iter() {
+        if let Some(value) = value {
+            writeln!(&mut stdout, "    {}: {}", label, value)?;
+        }
+    }
+    writeln!(&mut stdout, "    Used in: {} files", used_in)
+}
+
+fn display_explanation(f: &Filter, doc: &Document, or: bool) -> Result<(), std::io::Error> {
+    let explanation = f.explain_document(doc);
+    let mut stdout = std::io::stdout();
+    writeln!(
+        &mut stdout,
+        "{}: {} ({} keywords needed)",
+        doc.path,
+        if explanation.matches { "matches" } else { "doesn't match" },
+        if or { "ANY" } else { "ALL" }
+    )?;
+    writeln!(&mut stdout, "Tags:")?;
+    for tag in &explanation.tags {
+        let mut name = format!("@{}", tag.tag.join("/"));
+        if tag.canonical != tag.tag {
+            name = format!("{} ({})", name, tag.canonical.join("/"));
+        }
+        let mut notes = Vec::new();
+        if !tag.satisfied.is_empty() {
+            notes.push(format!("satisfies {}", tag.satisfied.join(", ")));
+        }
+        if !tag.violated.is_empty() {
+            notes.push(format!("ruled out by --not {}", tag.violated.join(", ")));
+        }
+        let line = format!("    {:24} {}", name, notes.join("; "));
+        writeln!(&mut stdout, "{}", line.trim_end())?;
+    }
+    if !explanation.keywords.is_empty() {
+        writeln!(&mut stdout, "Keywords:")?;
+        for (keyword, satisfied) in &explanation.keywords {
+            let status = if *satisfied { "satisfied" } else { "not satisfied by any tag" };
+            writeln!(&mut stdout, "    {:24} {}", keyword, status)?;
+        }
+    }
+    if !explanation.conditions.is_empty() {
+        writeln!(&mut stdout, "Comparisons:")?;
+        for c in &explanation.conditions {
+            let (name, status) = match (c.bad, c.holds) {
+                (false, true) => (c.condition.to_string(), "holds"),
+                (false, false) => (c.condition.to_string(), "doesn't hold (or the field is missing)"),
+                (true, true) => (format!("--not {}", c.condition), "holds, ruling the file out"),
+                (true, false) => (format!("--not {}", c.condition), "doesn't hold"),
+            };
+            writeln!(&mut stdout, "    {:24} {}", name, status)?;
+        }
+    }
+    if !explanation.queries.is_empty() {
+        writeln!(&mut stdout, "Saved queries:")?;
+        for q in &explanation.queries {
+            let (name, status) = match (q.bad, q.matches) {
+                (false, true) => (q.name.to_string(), "matches"),
+                (false, false) => (q.name.to_string(), "doesn't match"),
+                (true, true) => (format!("--not {}", q.name), "matches, ruling the file out"),
+                (true, false) => (format!("--not {}", q.name), "doesn't match"),
+            };
+            writeln!(&mut stdout, "    {:24} {}", name, status)?;
+        }
+    }
+    if let Some(in_range) = explanation.date {
+        let status = if in_range { "in range" } else { "not in range (or no date found)" };
+        writeln!(&mut stdout, "Date: {}", status)?;
+    }
+    Ok(())
+}
+
+fn display_materialize(f: &Filter, corpus: &Corpus, outdir: &str, dry_run: bool) -> Result<(), std::io::Error> {
+    let files: Vec<(String, Set<Tag>)> = f
+        .documents_matching(corpus)
+        .iter()
+        .map(|doc| (doc.path.to_string(), f.canonical_tags(&doc.tags).into_owned()))
+        .collect();
+    let outdir = std::path::Path::new(outdir);
+    let changes = plan_changes(outdir, &view_links(&files))?;
+    let mut stdout = std::io::stdout();
+    for link in &changes.remove {
+        writeln!(&mut stdout, "- {}", link.display())?;
+    }
+    for (link, target) in &changes.create {
+        writeln!(&mut stdout, "+ {} -> {}", link.display(), target.display())?;
+    }
+    writeln!(
+        &mut stdout,
+        "{} links {}, {} removed, {} unchanged",
+        changes.create.len(),
+        if dry_run { "to make" } else { "made" },
+        changes.remove.len(),
+        changes.unchanged
+    )?;
+    if !dry_run {
+        apply_changes(outdir, &changes)?;
+    }
+    Ok(())
+}
+
+fn display_tag_count(f: Filter, corpus: &Corpus) -> Result<(), std::io::Error> {
+    for (count, key) in f.count_of_tag_sets(corpus.documents.iter().map(|doc| &doc.tags)) {
         writeln!(&mut std::io::stdout(), "{:5} {}", count, key)?;
     }
     Ok(())
 }
+
+fn display_history(tag: &str, repo: &str, rev: &str) -> Result<(), std::io::Error> {
+    let history = tag_history(repo, rev, tag)?;
+    let mut stdout = std::io::stdout();
+    let (first, last) = match (history.first_seen(), history.last_seen()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            writeln!(&mut stdout, "Tag `{}` never appears in the history of {}", tag, rev)?;
+            return Ok(());
+        }
+    };
+    for (label, commit) in [("First seen", first), ("Last seen", last)].iter() {
+        writeln!(
+            &mut stdout,
+            "{}: {} {} {}",
+            label,
+            format_unix_date(commit.time),
+            commit.id,
+            commit.summary
+        )?;
+    }
+
+    writeln!(&mut stdout, "\nChanges:")?;
+    for event in &history.events {
+        let sign = match event.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+        };
+        writeln!(
+            &mut stdout,
+            "{} {} {} {}",
+            format_unix_date(event.commit.time),
+            event.commit.id,
+            sign,
+            event.path
+        )?;
+    }
+
+    writeln!(&mut stdout, "\nFiles using tag:")?;
+    let mut last_count = 0;
+    for (commit, count) in &history.counts {
+        if *count != last_count {
+            writeln!(&mut stdout, "{} {} {:5}", format_unix_date(commit.time), commit.id, count)?;
+            last_count = *count;
+        }
+    }
+    Ok(())
+}
+
+fn display_diff(
+    before: &str,
+    after: &str,
+    repo: &str,
+    options: WalkOptions,
+    json: bool,
+) -> Result<(), std::io::Error> {
+    let (before, before_unreadable) = snapshot(before, repo, options)?;
+    let (after, after_unreadable) = snapshot(after, repo, options)?;
+    for e in before_unreadable.iter().chain(&after_unreadable) {
+        eprintln!("{}", e);
+    }
+    let d = diff(&before, &after);
+    let mut stdout = std::io::stdout();
+    if json {
+        serde_json::to_writer_pretty(&mut stdout, &d)?;
+        return writeln!(&mut stdout);
+    }
+    if !d.added_tags.is_empty() {
+        writeln!(&mut stdout, "Added tags:")?;
+        for tag in &d.added_tags {
+            writeln!(&mut stdout, "    + {}", tag)?;
+        }
+    }
+    if !d.removed_tags.is_empty() {
+        writeln!(&mut stdout, "Removed tags:")?;
+        for tag in &d.removed_tags {
+            writeln!(&mut stdout, "    - {}", tag)?;
+        }
+    }
+    if !d.files.is_empty() {
+        writeln!(&mut stdout, "Changed files:")?;
+        for file in &d.files {
+            let changes: Vec<String> = file
+                .added
+                .iter()
+                .map(|t| format!("+{}", t))
+                .chain(file.removed.iter().map(|t| format!("-{}", t)))
+                .collect();
+            writeln!(&mut stdout, "    {}: {}", file.path, changes.join(" "))?;
+        }
+    }
+    if !d.counts.is_empty() {
+        writeln!(&mut stdout, "Count changes:")?;
+        for count in &d.counts {
+            writeln!(
+                &mut stdout,
+                "    {:5} -> {:5} ({:+}) {}",
+                count.before,
+                count.after,
+                count.delta(),
+                count.tag
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn display_lint(
+    files: &[String],
+    file_vaults: Option<&Map<String, String>>,
+    config: &Config,
+    json: bool,
+) -> Result<(), std::io::Error> {
+    // Each vault is linted separately, as each can have its own settings
+    let mut by_vault: Map<&str, Vec<&String>> = Map::new();
+    for path in files {
+        let vault = file_vaults
+            .and_then(|v| v.get(path))
+            .map_or(".", |v| v.as_str());
+        by_vault.entry(vault).or_default().push(path);
+    }
+    let mut diagnostics = Vec::new();
+    for (vault, vault_files) in by_vault {
+        let root = config
+            .vault_root(vault)
+            .unwrap_or_else(|_| vault.to_string());
+        // Each file's text is only needed while finding its tags, so isn't kept
+        let lint_files: Vec<LintFile> = vault_files
+            .par_iter()
+            .filter_map(|path| match Document::read(path, Keep::Text) {
+                Ok(doc) => Some(LintFile::from_contents(&doc.path, &root, doc.contents())),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    None
+                }
+            })
+            .collect();
+        let lint_config = config.lint_for(vault);
+        let similar = SimilarTags {
+            synonyms: config.similar.synonyms.clone(),
+            aliases: config.aliases.clone(),
+        };
+        let mut linter = Linter::new(lint_config, similar);
+        let deprecated = load_registry(&root, config)?.deprecated();
+        if !deprecated.is_empty() {
+            linter.add_rule(Box::new(Deprecated(deprecated)), lint_config);
+        }
+        diagnostics.extend(linter.lint(&lint_files));
+    }
+
+    let mut stdout = std::io::stdout();
+    if json {
+        serde_json::to_writer_pretty(&mut stdout, &diagnostics)?;
+        writeln!(&mut stdout)?;
+    } else {
+        for diagnostic in &diagnostics {
+            writeln!(&mut stdout, "{}", diagnostic)?;
+        }
+    }
+    if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+        stdout.flush()?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+use tagsearch::corpus::{Corpus, Document, Keep};
+use tagsearch::{utility::*, Tag};
+
+#[derive(PartialEq)]
+enum Focus {
+    Tags,
+    Files,
+}
+
+/// State for the interactive browser
+///
+/// Every file is read once up front, into a `Corpus`. The tag tree is stored as a map from
+/// each tag prefix to the number of files using it; as `Vec<String>` sorts
+/// lexicographically, iterating the map gives a depth-first walk of the tree.
+struct App {
+    corpus: Corpus,
+    tree: Map<Tag, usize>,
+    expanded: Set<Tag>,
+    selected: Set<Tag>,
+    filter: String,
+    typing: bool,
+    focus: Focus,
+    tag_state: ListState,
+    file_state: ListState,
+    /// Files within every wanted tag (see `wanted_tags`)
+    files: Vec<String>,
+    /// The wanted tags `files` was listed for, if it's up to date
+    files_for: Option<Vec<Tag>>,
+    preview_scroll: u16,
+    preview: Option<(String, String)>,
+    /// Shown in the status line until the next keypress, e.g. an error
+    message: Option<String>,
+}
+
+/// Every tag prefix in the corpus, with how many files use it
+fn tag_tree(corpus: &Corpus) -> Map<Tag, usize> {
+    let mut tree = Map::new();
+    for doc in &corpus.documents {
+        let prefixes: Set<Tag> = doc
+            .tags
+            .iter()
+            .flat_map(|tag| (1..=tag.len()).map(move |i| tag[..i].to_vec()))
+            .collect();
+        for prefix in prefixes {
+            *tree.entry(prefix).or_insert(0) += 1;
+        }
+    }
+    tree
+}
+
+impl App {
+    fn new(corpus: Corpus) -> App {
+        let mut app = App {
+            tree: tag_tree(&corpus),
+            corpus,
+            expanded: Set::new(),
+            selected: Set::new(),
+            filter: String::new(),
+            typing: false,
+            focus: Focus::Tags,
+            tag_state: ListState::default(),
+            file_state: ListState::default(),
+            files: Vec::new(),
+            files_for: None,
+            preview_scroll: 0,
+            preview: None,
+            message: None,
+        };
+        app.tag_state.select(Some(0));
+        app.file_state.select(Some(0));
+        app.update_files();
+        app
+    }
+
+    /// Tags currently shown in the tree pane, in display order
+    ///
+    /// While a filter is active, every tag whose path contains the filter is
+    /// shown along with its ancestors, regardless of which nodes are collapsed.
+    fn visible_tags(&self) -> Vec<&Tag> {
+        if self.filter.is_empty() {
+            return self
+                .tree
+                .keys()
+                .filter(|tag| (1..tag.len()).all(|i| self.expanded.contains(&tag[..i])))
+                .collect();
+        }
+        let needle = self.filter.to_lowercase();
+        let mut shown: Set<&[String]> = Set::new();
+        for tag in self.tree.keys() {
+            if tag.join("/").to_lowercase().contains(&needle) {
+                shown.extend((1..=tag.len()).map(|i| &tag[..i]));
+            }
+        }
+        self.tree
+            .keys()
+            .filter(|tag| shown.contains(tag.as_slice()))
+            .collect()
+    }
+
+    fn has_children(&self, tag: &Tag) -> bool {
+        self.tree
+            .range(tag.clone()..)
+            .nth(1)
+            .is_some_and(|(next, _)| next.starts_with(tag))
+    }
+
+    fn current_tag(&self) -> Option<Tag> {
+        let visible = self.visible_tags();
+        self.tag_state
+            .selected()
+            .and_then(|i| visible.get(i))
+            .map(|tag| tag.to_vec())
+    }
+
+    /// Every selected tag, or the tag under the cursor if nothing has been
+    /// selected yet
+    fn wanted_tags(&self) -> Vec<Tag> {
+        if self.selected.is_empty() {
+            self.current_tag().into_iter().collect()
+        } else {
+            self.selected.iter().cloned().collect()
+        }
+    }
+
+    /// List the files with a tag within every wanted tag, as counted in the
+    /// tree, unless they're already listed for the same tags
+    fn update_files(&mut self) {
+        let wanted = self.wanted_tags();
+        if self.files_for.as_ref() == Some(&wanted) {
+            return;
+        }
+        self.files = if wanted.is_empty() {
+            Vec::new()
+        } else {
+            self.corpus
+                .documents
+                .iter()
+                .filter(|doc| {
+                    wanted
+                        .iter()
+                        .all(|w| doc.tags.iter().any(|tag| tag_is_within(tag, w)))
+                })
+                .map(|doc| doc.path.clone())
+                .collect()
+        };
+        self.files_for = Some(wanted);
+    }
+
+    fn current_file(&self) -> Option<String> {
+        self.file_state
+            .selected()
+            .and_then(|i| self.files.get(i))
+            .cloned()
+    }
+
+    /// Read the highlighted file, scrolling to its first tagged line
+    ///
+    /// Only the previewed file's text is kept, as the rest of the corpus only
+    /// needs tags.
+    fn refresh_preview(&mut self) {
+        let fname = match self.current_file() {
+            Some(fname) => fname,
+            None => {
+                self.preview = None;
+                return;
+            }
+        };
+        if matches!(&self.preview, Some((name, _)) if *name == fname) {
+            return;
+        }
+        let doc = match Document::read(&fname, Keep::Text) {
+            Ok(doc) => doc,
+            Err(e) => {
+                self.message = Some(e.to_string());
+                self.preview = Some((fname, String::new()));
+                return;
+            }
+        };
+        let first_tag_line = doc.occurrences().first().map_or(0, |occurrence| occurrence.line - 1);
+        self.preview_scroll = first_tag_line.saturating_sub(2) as u16;
+        self.preview = Some((fname, doc.contents().to_string()));
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let len = match self.focus {
+            Focus::Tags => self.visible_tags().len(),
+            Focus::Files => self.files.len(),
+        };
+        let state = match self.focus {
+            Focus::Tags => &mut self.tag_state,
+            Focus::Files => &mut self.file_state,
+        };
+        if len == 0 {
+            state.select(Some(0));
+            return;
+        }
+        let current = state.selected().unwrap_or(0) as isize;
+        let next = (current + delta).clamp(0, len as isize - 1);
+        state.select(Some(next as usize));
+        if self.focus == Focus::Tags {
+            self.file_state.select(Some(0));
+        }
+    }
+
+    fn set_expanded(&mut self, expand: bool) {
+        if let Some(tag) = self.current_tag() {
+            if expand {
+                self.expanded.insert(tag);
+            } else if !self.expanded.remove(&tag) && tag.len() > 1 {
+                // Collapsing a leaf jumps to, and collapses, its parent
+                let parent = tag[..tag.len() - 1].to_vec();
+                self.expanded.remove(&parent);
+                let idx = self.visible_tags().iter().position(|t| **t == parent);
+                self.tag_state.select(idx);
+            }
+        }
+    }
+
+    fn toggle_selected(&mut self) {
+        if let Some(tag) = self.current_tag() {
+            if !self.selected.remove(&tag) {
+                self.selected.insert(tag);
+            }
+            self.file_state.select(Some(0));
+        }
+    }
+
+    /// Open a file in the editor, then re-read it, as it may have been edited
+    fn edit(&mut self, terminal: &mut DefaultTerminal, fname: &str) -> std::io::Result<()> {
+        open_in_editor(terminal, fname).map_err(|e| {
+            std::io::Error::new(e.kind(), format!("Couldn't open `{}` in an editor: {}", fname, e))
+        })?;
+        self.corpus.replace(Document::read(fname, Keep::Tags)?);
+        self.tree = tag_tree(&self.corpus);
+        self.preview = None;
+        self.files_for = None;
+        self.update_files();
+        // The edit may have removed tags or files from the lists
+        let tags = self.visible_tags().len();
+        let files = self.files.len();
+        self.tag_state.select(Some(self.tag_state.selected().unwrap_or(0).min(tags.saturating_sub(1))));
+        self.file_state.select(Some(self.file_state.selected().unwrap_or(0).min(files.saturating_sub(1))));
+        Ok(())
+    }
+
+    /// Handle a keypress, returning `false` when the browser should exit
+    fn handle_key(&mut self, terminal: &mut DefaultTerminal, key: KeyCode) -> std::io::Result<bool> {
+        self.message = None;
+        if self.typing {
+            match key {
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.typing = false;
+                }
+                KeyCode::Enter => self.typing = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.tag_state.select(Some(0));
+            self.file_state.select(Some(0));
+            return Ok(true);
+        }
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Char('/') => {
+                self.typing = true;
+                self.focus = Focus::Tags;
+            }
+            KeyCode::Tab | KeyCode::BackTab => {
+                self.focus = match self.focus {
+                    Focus::Tags => Focus::Files,
+                    Focus::Files => Focus::Tags,
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+            KeyCode::Right | KeyCode::Char('l') if self.focus == Focus::Tags => {
+                self.set_expanded(true)
+            }
+            KeyCode::Left | KeyCode::Char('h') if self.focus == Focus::Tags => {
+                self.set_expanded(false)
+            }
+            KeyCode::Char(' ') if self.focus == Focus::Tags => self.toggle_selected(),
+            KeyCode::Char('c') => {
+                self.selected.clear();
+                self.file_state.select(Some(0));
+            }
+            KeyCode::PageDown | KeyCode::Char('J') => {
+                self.preview_scroll = self.preview_scroll.saturating_add(10)
+            }
+            KeyCode::PageUp | KeyCode::Char('K') => {
+                self.preview_scroll = self.preview_scroll.saturating_sub(10)
+            }
+            KeyCode::Enter if self.focus == Focus::Tags => self.focus = Focus::Files,
+            KeyCode::Enter | KeyCode::Char('e') => {
+                if let Some(fname) = self.current_file() {
+                    if let Err(e) = self.edit(terminal, &fname) {
+                        self.message = Some(e.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [tags_area, files_area, preview_area] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(30),
+            Constraint::Percentage(45),
+        ])
+        .areas(main);
+
+        let (tags_border, files_border) = match self.focus {
+            Focus::Tags => (Style::new().fg(Color::Cyan), Style::new()),
+            Focus::Files => (Style::new(), Style::new().fg(Color::Cyan)),
+        };
+
+        let tag_items: Vec<ListItem> = self
+            .visible_tags()
+            .into_iter()
+            .map(|tag| {
+                let marker = if !self.has_children(tag) {
+                    " "
+                } else if self.expanded.contains(tag) || !self.filter.is_empty() {
+                    "▾"
+                } else {
+                    "▸"
+                };
+                let mut line = Line::from(vec![
+                    Span::raw("  ".repeat(tag.len() - 1)),
+                    Span::raw(format!("{} ", marker)),
+                    Span::raw(tag[tag.len() - 1].to_string()),
+                    Span::raw(format!(" ({})", self.tree[tag])).dark_gray(),
+                ]);
+                if self.selected.contains(tag) {
+                    line = line.green().bold();
+                }
+                ListItem::new(line)
+            })
+            .collect();
+        let tag_list = List::new(tag_items)
+            .block(
+                Block::bordered()
+                    .title("Tags")
+                    .border_style(tags_border),
+            )
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(tag_list, tags_area, &mut self.tag_state);
+
+        let title = format!("Files ({})", self.files.len());
+        let file_list = List::new(self.files.iter().map(|f| ListItem::new(f.as_str())))
+            .block(
+                Block::bordered()
+                    .title(title)
+                    .border_style(files_border),
+            )
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(file_list, files_area, &mut self.file_state);
+
+        self.refresh_preview();
+        let (title, text) = match &self.preview {
+            Some((fname, contents)) => (fname.as_str(), highlight_tags(contents)),
+            None => ("Preview", Text::default()),
+        };
+        let preview = Paragraph::new(text)
+            .block(Block::bordered().title(title))
+            .scroll((self.preview_scroll, 0));
+        frame.render_widget(preview, preview_area);
+
+        let status_line = if let Some(message) = &self.message {
+            Line::from(message.as_str()).red()
+        } else if self.typing {
+            Line::from(format!("/{}", self.filter))
+        } else if !self.filter.is_empty() {
+            Line::from(format!("filter: {}  (/ to edit, Esc in filter to clear)", self.filter))
+        } else {
+            Line::from(
+                "q quit  / filter  tab switch pane  space select  h/l collapse/expand  c clear  e open  J/K scroll",
+            )
+            .dark_gray()
+        };
+        frame.render_widget(status_line, status);
+    }
+}
+
+/// Render file contents with a line-number gutter, tags in bold yellow
+fn highlight_tags(contents: &str) -> Text<'_> {
+    let width = contents.lines().count().to_string().len();
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let mut spans = vec![Span::raw(format!("{:>width$} ", i + 1, width = width)).dark_gray()];
+            let mut last = 0;
+            for occurrence in tag_occurrences(line) {
+                let range = occurrence.byte_range;
+                spans.push(Span::raw(&line[last..range.start]));
+                spans.push(Span::raw(&line[range.clone()]).yellow().bold());
+                last = range.end;
+            }
+            spans.push(Span::raw(&line[last..]));
+            Line::from(spans)
+        })
+        .collect()
+}
+
+/// Suspend the interface and open a file in `$EDITOR` (falling back to `vi`)
+fn open_in_editor(terminal: &mut DefaultTerminal, fname: &str) -> std::io::Result<()> {
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    ratatui::restore();
+    let status = std::process::Command::new(program)
+        .args(parts)
+        .arg(fname)
+        .status();
+    *terminal = ratatui::init();
+    terminal.clear()?;
+    status.map(|_| ())
+}
+
+/// Run the interactive tag browser over the given files
+pub fn run(corpus: Corpus) -> Result<(), std::io::Error> {
+    let mut app = App::new(corpus);
+    let mut terminal = ratatui::init();
+    let result = (|| loop {
+        terminal.draw(|frame| app.draw(frame))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(&mut terminal, key.code)? {
+                return Ok(());
+            }
+            app.update_files();
+        }
+    })();
+    ratatui::restore();
+    result
+}
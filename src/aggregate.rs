@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::str::FromStr;
+
+use super::Tag;
+use crate::metadata::Metadata;
+
+/// How to combine the values of a field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Count,
+}
+
+/// How to split files into groups, each aggregated separately
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Grouping {
+    /// Every file in one group
+    All,
+    /// A group for each tag, containing every file with that tag
+    Tag,
+    /// A group for each `YYYY-MM` month, taken from a date field
+    Month(String),
+}
+
+impl FromStr for Grouping {
+    type Err = String;
+
+    /// Parse `tag` or `month`, or `month:FIELD` to take dates from a field
+    /// other than `date`
+    fn from_str(s: &str) -> Result<Grouping, String> {
+        match s.split_once(':') {
+            None if s == "tag" => Ok(Grouping::Tag),
+            None if s == "month" => Ok(Grouping::Month("date".to_string())),
+            Some(("month", field)) => Ok(Grouping::Month(field.to_lowercase())),
+            _ => Err(format!("Can't group by `{}`, expected `tag`, `month`, or `month:FIELD`", s)),
+        }
+    }
+}
+
+/// Running totals of a numeric field
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Default for Summary {
+    fn default() -> Summary {
+        Summary {
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Summary {
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    /// The aggregate value, or `None` for the average, min, or max of nothing
+    pub fn get(&self, aggregate: Aggregate) -> Option<f64> {
+        match aggregate {
+            Aggregate::Count => Some(self.count as f64),
+            Aggregate::Sum => Some(self.sum),
+            _ if self.count == 0 => None,
+            Aggregate::Avg => Some(self.sum / self.count as f64),
+            Aggregate::Min => Some(self.min),
+            Aggregate::Max => Some(self.max),
+        }
+    }
+}
+
+/// Summarise a numeric field across files, given each file's tags and metadata
+///
+/// Files without the field, or where it isn't a number, are skipped. With
+/// `Grouping::All` the only group is named `total`; files without a date
+/// when grouping by month are grouped as `unknown`.
+pub fn summarise<'a, I>(files: I, field: &str, grouping: &Grouping) -> Map<String, Summary>
+where
+    I: IntoIterator<Item = (&'a Set<Tag>, &'a Metadata)>,
+{
+    let field = field.to_lowercase();
+    let mut groups: Map<String, Summary> = Map::new();
+    for (tags, metadata) in files {
+        let value = match metadata.get(&field).and_then(|v| v.as_number()) {
+            Some(value) => value,
+            None => continue,
+        };
+        let names: Set<String> = match grouping {
+            Grouping::All => ["total".to_string()].iter().cloned().collect(),
+            Grouping::Tag => tags.iter().map(|t| t.join("/")).collect(),
+            Grouping::Month(date_field) => {
+                let month = metadata
+                    .get(date_field)
+                    .and_then(|v| v.as_date())
+                    .map_or_else(|| "unknown".to_string(), |d| d.month_string());
+                [month].iter().cloned().collect()
+            }
+        };
+        for name in names {
+            groups.entry(name).or_default().add(value);
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::metadata_from_string;
+    use crate::utility::get_tags_from_string;
+
+    #[test]
+    fn summarise_groups() {
+        let files: Vec<(Set<Tag>, Metadata)> = [
+            "value: 42\ndate: 2024-03-01\n\n@monthly @health",
+            "value: 12.5\ndate: 2024-03-20\n\n@monthly @fun",
+            "value: 8\ndate: 2024-04-02\n\n@monthly @fun",
+            "value: lots\n\n@monthly",
+            "date: 2024-04-02\n\n@monthly",
+        ]
+        .iter()
+        .map(|contents| (get_tags_from_string(contents), metadata_from_string(contents)))
+        .collect();
+        let entries = || files.iter().map(|(t, m)| (t, m));
+
+        let total = summarise(entries(), "Value", &Grouping::All)["total"];
+        assert_eq!(total.get(Aggregate::Sum), Some(62.5));
+        assert_eq!(total.get(Aggregate::Count), Some(3.0));
+        assert_eq!(total.get(Aggregate::Min), Some(8.0));
+        assert_eq!(total.get(Aggregate::Max), Some(42.0));
+
+        let by_tag = summarise(entries(), "value", &Grouping::Tag);
+        assert_eq!(by_tag["fun"].get(Aggregate::Avg), Some(10.25));
+        assert_eq!(by_tag["monthly"].count, 3);
+
+        let by_month = summarise(entries(), "value", &"month".parse().unwrap());
+        assert_eq!(by_month["2024-03"].sum, 54.5);
+        assert_eq!(by_month["2024-04"].sum, 8.0);
+        assert!("week".parse::<Grouping>().is_err());
+
+        assert_eq!(Summary::default().get(Aggregate::Avg), None);
+    }
+}
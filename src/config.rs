@@ -0,0 +1,280 @@
+use std::collections::BTreeMap as Map;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+use serde::Deserialize;
+
+use crate::dates::{default_date_sources, DateSource};
+use crate::lint::LintConfig;
+use crate::query::Query;
+
+/// Settings read from the user's config file
+///
+/// The config file is TOML, and is looked for at `$TAGSEARCH_CONFIG`, then
+/// `$XDG_CONFIG_HOME/tagsearch/config.toml`, then
+/// `~/.config/tagsearch/config.toml`. A missing file is the same as an empty
+/// one.
+///
+/// ```toml
+/// registry = "~/notes/tags.toml"
+///
+/// [vaults.personal]
+/// root = "~/notes"
+///
+/// [vaults.team]
+/// root = "/shared/team-notes"
+///
+/// [lint]
+/// max_depth = 3
+///
+/// [similar]
+/// synonyms = [["ai", "artificial-intelligence"]]
+///
+/// [aliases]
+/// ml = "machine-learning"
+/// js = "javascript"
+///
+/// [dates]
+/// sources = ["filename", "field:created", "mtime"]
+///
+/// [queries]
+/// reading = "unread AND neuralnet AND NOT archived"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub vaults: Map<String, Vault>,
+    /// Lint settings for vaults without their own
+    pub lint: LintConfig,
+    pub similar: SimilarConfig,
+    /// Other spellings of tags, mapped to the tag's canonical name
+    pub aliases: Map<String, String>,
+    /// Tag registry to use, instead of `tags.toml` in the root being searched
+    pub registry: Option<String>,
+    pub dates: DatesConfig,
+    /// Saved queries, by name (see `Query` for the syntax)
+    pub queries: Map<String, String>,
+}
+
+/// Settings for finding the dates of files
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatesConfig {
+    /// Where to look for a file's date, in order, e.g. `["filename", "mtime"]`
+    pub sources: Option<Vec<DateSource>>,
+}
+
+/// Settings for finding similar tags
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimilarConfig {
+    /// Groups of words that mean the same thing
+    pub synonyms: Vec<Vec<String>>,
+}
+
+/// A named directory of notes
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Vault {
+    pub root: String,
+    /// Lint settings for this vault, replacing the top-level `[lint]` table
+    pub lint: Option<LintConfig>,
+}
+
+impl Config {
+    /// Load the config from `path`, or from the default location if `None`
+    ///
+    /// An explicitly given `path` must exist.
+    pub fn load(path: Option<&str>) -> Result<Config, Error> {
+        let path = match path {
+            Some(path) => PathBuf::from(path),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+        let contents = std::fs::read_to_string(&path).map_err(|e| {
+            Error::new(e.kind(), format!("Couldn't read config `{}`: {}", path.display(), e))
+        })?;
+        Config::parse(&contents).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid config `{}`: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Parse a config from a TOML string
+    pub fn parse(contents: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// Get the root directory of a named vault, with `~` expanded
+    pub fn vault_root(&self, name: &str) -> Result<String, Error> {
+        match self.vaults.get(name) {
+            Some(vault) => Ok(expand_tilde(&vault.root)),
+            None => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No vault named `{}` in config", name),
+            )),
+        }
+    }
+
+    /// The registry file given in the config, with `~` expanded
+    pub fn registry_path(&self) -> Option<String> {
+        self.registry.as_deref().map(expand_tilde)
+    }
+
+    /// Where to look for the dates of files, in order
+    pub fn date_sources(&self) -> Vec<DateSource> {
+        self.dates.sources.clone().unwrap_or_else(default_date_sources)
+    }
+
+    /// Parse every saved query
+    pub fn saved_queries(&self) -> Result<Map<String, Query>, Error> {
+        self.queries
+            .iter()
+            .map(|(name, query)| {
+                let query = query.parse().map_err(|e: Error| {
+                    Error::new(e.kind(), format!("Saved query `{}`: {}", name, e))
+                })?;
+                Ok((name.to_string(), query))
+            })
+            .collect()
+    }
+
+    /// Get the lint settings for a vault, or the top-level settings if the
+    /// vault has none (or isn't a configured vault)
+    pub fn lint_for(&self, vault: &str) -> &LintConfig {
+        match self.vaults.get(vault).and_then(|v| v.lint.as_ref()) {
+            Some(lint) => lint,
+            None => &self.lint,
+        }
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("TAGSEARCH_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+    let config_dir = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+    };
+    Some(config_dir.join("tagsearch").join("config.toml"))
+}
+
+fn expand_tilde(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home, rest),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_aliases() {
+        let config = Config::parse(
+            r#"
+            [aliases]
+            ml = "machine-learning"
+            js = "programming/javascript"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.aliases["ml"], "machine-learning");
+        assert_eq!(config.aliases["js"], "programming/javascript");
+    }
+
+    #[test]
+    fn parse_date_sources() {
+        let config = Config::parse("[dates]\nsources = [\"filename\", \"field:created\"]").unwrap();
+        assert_eq!(
+            config.date_sources(),
+            vec![DateSource::Filename, DateSource::Field("created".to_string())]
+        );
+        assert_eq!(Config::default().date_sources(), default_date_sources());
+        assert!(Config::parse("[dates]\nsources = [\"yesterday\"]").is_err());
+    }
+
+    #[test]
+    fn parse_vaults() {
+        let config = Config::parse(
+            r#"
+            [vaults.personal]
+            root = "/home/me/notes"
+
+            [vaults.team]
+            root = "/shared/team"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.vault_root("team").unwrap(), "/shared/team");
+        assert_eq!(config.vault_root("personal").unwrap(), "/home/me/notes");
+        assert!(config.vault_root("missing").is_err());
+    }
+
+    #[test]
+    fn per_vault_lint() {
+        let config = Config::parse(
+            r#"
+            [lint]
+            max_depth = 3
+
+            [vaults.team]
+            root = "/shared/team"
+            [vaults.team.lint]
+            max_depth = 1
+
+            [vaults.personal]
+            root = "/home/me/notes"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.lint_for("team").max_depth, Some(1));
+        assert_eq!(config.lint_for("personal").max_depth, Some(3));
+        assert_eq!(config.lint_for(".").max_depth, Some(3));
+
+        let err = Config::parse("[lint.severity]\nsingletons = \"off\"").unwrap_err();
+        assert!(err.to_string().contains("`singletons`"));
+        let err = Config::parse(
+            r#"
+            [vaults.team]
+            root = "/shared/team"
+            [vaults.team.lint.severity]
+            depth = "error"
+            similiar = "off"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("`similiar`"));
+    }
+
+    #[test]
+    fn parse_saved_queries() {
+        let config = Config::parse(
+            r#"
+            [queries]
+            reading = "unread AND neuralnet AND NOT archived"
+            spending = "monthly value>20"
+            "#,
+        )
+        .unwrap();
+        let queries = config.saved_queries().unwrap();
+        assert_eq!(queries["reading"].bad, vec!["archived"]);
+        assert_eq!(queries["spending"].good, vec!["monthly", "value>20"]);
+
+        let config = Config::parse("[queries]\nbroken = \"a AND NOT\"").unwrap();
+        let error = config.saved_queries().unwrap_err().to_string();
+        assert!(error.contains("broken"));
+    }
+
+    #[test]
+    fn empty_config() {
+        let config = Config::parse("").unwrap();
+        assert!(config.vaults.is_empty());
+    }
+}
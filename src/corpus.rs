@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::io::Error;
+
+use rayon::prelude::*;
+
+use super::Tag;
+use crate::metadata::{metadata_from_string, Metadata};
+use crate::utility::{get_tags_from_bytes, read_bytes, tag_occurrences, TagOccurrence};
+
+/// How much of each file to keep once it's been read
+///
+/// Tags are always kept. Metadata is only parsed when something compares,
+/// shows, or dates by it, and only commands that show or search a file's text
+/// (e.g. `--grep`, `--vim`, the tui's preview) need it all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Keep {
+    Tags,
+    Metadata,
+    Text,
+}
+
+/// A file's text, and every tag in it
+#[derive(Debug, Clone)]
+pub struct Text {
+    pub contents: String,
+    /// Every tag in the file, in the order they appear
+    pub occurrences: Vec<TagOccurrence>,
+}
+
+/// A file, read and tokenised once
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub path: String,
+    /// The distinct tags in the file
+    pub tags: Set<Tag>,
+    pub metadata: Metadata,
+    /// Size of the file, in bytes
+    pub size: usize,
+    /// The file's text, if it was kept (see `Keep`)
+    pub text: Option<Text>,
+}
+
+impl Document {
+    /// Read a file (`-` for standard input)
+    ///
+    /// Tags are found in the file's raw bytes, so files that aren't valid
+    /// UTF-8 can still be read. Their metadata and text are read lossily.
+    pub fn read(path: &str, keep: Keep) -> Result<Document, Error> {
+        let bytes = read_bytes(path).map_err(|e| {
+            Error::new(e.kind(), format!("Couldn't read contents of file `{}`: {}", path, e))
+        })?;
+        let doc = match keep {
+            Keep::Text => {
+                Document::from_contents(path, String::from_utf8_lossy(&bytes).into_owned())
+            }
+            Keep::Tags | Keep::Metadata => Document {
+                path: path.to_string(),
+                tags: get_tags_from_bytes(&bytes),
+                metadata: if keep == Keep::Metadata {
+                    metadata_from_string(&String::from_utf8_lossy(&bytes))
+                } else {
+                    Metadata::new()
+                },
+                size: 0,
+                text: None,
+            },
+        };
+        Ok(Document {
+            size: bytes.len(),
+            ..doc
+        })
+    }
+
+    pub fn from_contents(path: &str, contents: String) -> Document {
+        let occurrences: Vec<TagOccurrence> = tag_occurrences(&contents).collect();
+        let tags = occurrences.iter().map(|o| o.tag.clone()).collect();
+        let metadata = metadata_from_string(&contents);
+        Document {
+            path: path.to_string(),
+            tags,
+            metadata,
+            size: contents.len(),
+            text: Some(Text { contents, occurrences }),
+        }
+    }
+
+    /// Read the file again, this time keeping its text
+    pub fn load_text(&mut self) -> Result<(), Error> {
+        *self = Document::read(&self.path, Keep::Text)?;
+        Ok(())
+    }
+
+    /// The file's text, or nothing if it wasn't kept
+    pub fn contents(&self) -> &str {
+        self.text.as_ref().map_or("", |text| &text.contents)
+    }
+
+    /// Every tag in the file in the order they appear, or none if the text
+    /// wasn't kept
+    pub fn occurrences(&self) -> &[TagOccurrence] {
+        self.text.as_ref().map_or(&[], |text| &text.occurrences)
+    }
+}
+
+/// Every file searched in a run, each read once up front
+///
+/// Reading is done in parallel, and documents are kept in the order the
+/// files were given, so everything that needs a file's tags, metadata, or
+/// contents can share the one read.
+#[derive(Debug, Default)]
+pub struct Corpus {
+    pub documents: Vec<Document>,
+    /// Why each file that couldn't be read was skipped
+    pub unreadable: Vec<Error>,
+    index: Map<String, usize>,
+}
+
+impl Corpus {
+    /// Read every file, skipping any that can't be read (e.g. if they were
+    /// removed since being found), and recording why in `unreadable`
+    pub fn read(files: &[String], keep: Keep) -> Corpus {
+        let read: Vec<Result<Document, Error>> = files
+            .par_iter()
+            .map(|path| Document::read(path, keep))
+            .collect();
+        let (documents, unreadable) = split_unreadable(read);
+        Corpus {
+            unreadable,
+            ..Corpus::from_documents(documents)
+        }
+    }
+
+    /// Read the text of only the documents that are `wanted` (e.g. those whose
+    /// tags match a filter), so the text of every other file is never kept
+    ///
+    /// Documents that can no longer be read are dropped, recording why in
+    /// `unreadable`.
+    pub fn load_text<P>(&mut self, wanted: P)
+    where
+        P: Fn(&Document) -> bool + Sync,
+    {
+        let failed: Map<usize, Error> = self
+            .documents
+            .par_iter_mut()
+            .enumerate()
+            .filter(|(_, doc)| doc.text.is_none() && wanted(doc))
+            .filter_map(|(i, doc)| doc.load_text().err().map(|e| (i, e)))
+            .collect();
+        if failed.is_empty() {
+            return;
+        }
+        let documents = std::mem::take(&mut self.documents)
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| !failed.contains_key(i))
+            .map(|(_, doc)| doc)
+            .collect();
+        let mut unreadable = std::mem::take(&mut self.unreadable);
+        unreadable.extend(failed.into_values());
+        *self = Corpus {
+            unreadable,
+            ..Corpus::from_documents(documents)
+        };
+    }
+
+    pub fn from_documents(documents: Vec<Document>) -> Corpus {
+        let index = documents
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| (doc.path.clone(), i))
+            .collect();
+        Corpus {
+            documents,
+            unreadable: Vec::new(),
+            index,
+        }
+    }
+
+    /// Replace the document with the same path (e.g. after the file was
+    /// edited), or add it if there isn't one
+    pub fn replace(&mut self, doc: Document) {
+        match self.index.get(&doc.path) {
+            Some(&i) => self.documents[i] = doc,
+            None => {
+                self.index.insert(doc.path.clone(), self.documents.len());
+                self.documents.push(doc);
+            }
+        }
+    }
+
+    pub fn get(&self, path: &str) -> Option<&Document> {
+        self.index.get(path).map(|&i| &self.documents[i])
+    }
+
+    pub fn paths(&self) -> Vec<String> {
+        self.documents.iter().map(|doc| doc.path.clone()).collect()
+    }
+
+    /// Every distinct tag in any file
+    pub fn tags(&self) -> Set<Tag> {
+        self.documents
+            .iter()
+            .flat_map(|doc| doc.tags.iter().cloned())
+            .collect()
+    }
+}
+
+fn split_unreadable(read: Vec<Result<Document, Error>>) -> (Vec<Document>, Vec<Error>) {
+    let mut unreadable = Vec::new();
+    let documents = read
+        .into_iter()
+        .filter_map(|doc| doc.map_err(|e| unreadable.push(e)).ok())
+        .collect();
+    (documents, unreadable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::parse_heirarchical_tag as tagparse;
+    use crate::utility::{find_files, WalkOptions};
+
+    #[test]
+    fn documents_are_tokenised_once() {
+        let doc = Document::from_contents(
+            "notes/a.md",
+            "value: 42\n\n@rust/async @notes\nmore @rust/async".to_string(),
+        );
+        assert_eq!(doc.occurrences().len(), 3);
+        assert_eq!(doc.tags.len(), 2);
+        assert_eq!(doc.metadata["value"].as_number(), Some(42.0));
+
+        let other = Document::from_contents("b.md", "@python".to_string());
+        let mut corpus = Corpus::from_documents(vec![doc, other]);
+        assert_eq!(corpus.paths(), vec!["notes/a.md", "b.md"]);
+        assert!(corpus.get("b.md").unwrap().tags.contains(&tagparse("python")));
+        assert!(corpus.get("c.md").is_none());
+        assert_eq!(corpus.tags().len(), 3);
+
+        corpus.replace(Document::from_contents("b.md", "@python @rust/async".to_string()));
+        corpus.replace(Document::from_contents("c.md", String::new()));
+        assert_eq!(corpus.paths(), vec!["notes/a.md", "b.md", "c.md"]);
+        assert_eq!(corpus.get("b.md").unwrap().tags.len(), 2);
+    }
+
+    #[test]
+    fn text_is_only_kept_when_asked_for() {
+        let path = std::env::temp_dir().join(format!("tagsearch-keep-{}.md", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        std::fs::write(&path, b"value: 4\xff2\n\n@a \xff@b @c @a").unwrap();
+
+        let tags = Document::read(&path_str, Keep::Tags).unwrap();
+        assert!(tags.text.is_none());
+        assert_eq!(tags.size, 24);
+        assert!(tags.metadata.is_empty());
+
+        let metadata = Document::read(&path_str, Keep::Metadata).unwrap();
+        assert!(metadata.text.is_none());
+        assert_eq!(metadata.tags, tags.tags);
+        assert_eq!(metadata.metadata["value"].to_string(), "4\u{fffd}2");
+
+        let text = Document::read(&path_str, Keep::Text).unwrap();
+        assert_eq!(text.occurrences().len(), 3);
+        assert_eq!(text.size, tags.size);
+        assert_eq!(text.tags, tags.tags);
+        assert_eq!(text.tags, [tagparse("a"), tagparse("c")].iter().cloned().collect());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let root = std::env::temp_dir().join(format!("tagsearch-corpus-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        for (i, name) in ["z.md", "a.txt", "sub/m.org"].iter().enumerate() {
+            std::fs::write(root.join(name), format!("@tag{} @shared", i)).unwrap();
+        }
+        let found = find_files(&root.to_string_lossy(), WalkOptions::default()).unwrap();
+        let mut files = found.clone();
+        files.insert(1, root.join("gone.md").to_string_lossy().into_owned());
+        let corpus = Corpus::read(&files, Keep::Tags);
+        assert_eq!(corpus.paths(), found);
+        assert_eq!(corpus.tags().len(), 4);
+        assert_eq!(corpus.unreadable.len(), 1);
+        assert!(corpus.unreadable[0].to_string().contains("gone.md"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn text_is_only_loaded_for_wanted_documents() {
+        let root = std::env::temp_dir().join(format!("tagsearch-load-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let files: Vec<String> = ["a.md", "b.md", "c.md"]
+            .iter()
+            .map(|name| {
+                let path = root.join(name);
+                std::fs::write(&path, format!("@{} @shared", &name[..1])).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let mut corpus = Corpus::read(&files, Keep::Tags);
+        std::fs::remove_file(&files[2]).unwrap();
+
+        let wanted = |doc: &Document| !doc.tags.contains(&tagparse("a"));
+        corpus.load_text(wanted);
+        assert_eq!(corpus.paths(), &files[..2]);
+        assert!(corpus.get(&files[0]).unwrap().text.is_none());
+        assert_eq!(corpus.get(&files[1]).unwrap().contents(), "@b @shared");
+        assert_eq!(corpus.unreadable.len(), 1);
+        assert!(corpus.unreadable[0].to_string().contains("c.md"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
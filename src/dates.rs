@@ -0,0 +1,153 @@
+use std::convert::TryFrom;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+
+use crate::metadata::{Date, Metadata};
+
+/// Where to find the date of a file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum DateSource {
+    /// A `YYYYMMDD` or `YYYY-MM-DD` date in the file name, e.g.
+    /// `20191210--shoulderpress.txt`
+    Filename,
+    /// A metadata field, e.g. `date: 2024-03-01` in the header
+    Field(String),
+    /// The first date anywhere in the file
+    Content,
+    /// When the file was last modified
+    Modified,
+}
+
+impl FromStr for DateSource {
+    type Err = String;
+
+    /// Parse `filename`, `field` (the `date` field), `field:NAME`, `content`,
+    /// or `mtime`
+    fn from_str(s: &str) -> Result<DateSource, String> {
+        match s.split_once(':') {
+            None if s == "filename" => Ok(DateSource::Filename),
+            None if s == "field" => Ok(DateSource::Field("date".to_string())),
+            None if s == "content" => Ok(DateSource::Content),
+            None if s == "mtime" => Ok(DateSource::Modified),
+            Some(("field", name)) => Ok(DateSource::Field(name.to_lowercase())),
+            _ => Err(format!(
+                "Unknown date source `{}`, expected `filename`, `field`, `field:NAME`, `content`, or `mtime`",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for DateSource {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<DateSource, String> {
+        s.parse()
+    }
+}
+
+/// The default order to look for a file's date in
+pub fn default_date_sources() -> Vec<DateSource> {
+    vec![
+        DateSource::Filename,
+        DateSource::Field("date".to_string()),
+        DateSource::Content,
+        DateSource::Modified,
+    ]
+}
+
+/// Get the date of a file from the first of `sources` that has one
+///
+/// `metadata` is only looked at for `Field` sources, and `contents` for
+/// `Content` sources.
+pub fn file_date(filename: &str, contents: &str, metadata: &Metadata, sources: &[DateSource]) -> Option<Date> {
+    sources.iter().find_map(|source| match source {
+        DateSource::Filename => Path::new(filename)
+            .file_name()
+            .and_then(|name| Date::find_in(&name.to_string_lossy())),
+        DateSource::Field(name) => metadata.get(name).and_then(|value| value.as_date()),
+        DateSource::Content => Date::find_in(contents),
+        DateSource::Modified => {
+            let modified = std::fs::metadata(filename).and_then(|m| m.modified()).ok()?;
+            let seconds = modified.duration_since(UNIX_EPOCH).ok()?.as_secs();
+            Some(Date::from_unix(seconds as i64))
+        }
+    })
+}
+
+/// Whether any of `sources` need a file's contents to find its date
+pub fn sources_need_contents(sources: &[DateSource]) -> bool {
+    sources.contains(&DateSource::Content)
+}
+
+/// Whether any of `sources` need a file's metadata to find its date
+pub fn sources_need_metadata(sources: &[DateSource]) -> bool {
+    sources.iter().any(|s| matches!(s, DateSource::Field(_)))
+}
+
+/// An inclusive range of dates, either end of which may be open
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub since: Option<Date>,
+    pub until: Option<Date>,
+}
+
+impl DateRange {
+    /// A range covering only a single day
+    pub fn on(date: Date) -> DateRange {
+        DateRange {
+            since: Some(date),
+            until: Some(date),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    pub fn contains(&self, date: Date) -> bool {
+        self.since.is_none_or(|since| date >= since) && self.until.is_none_or(|until| date <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::metadata_from_string;
+
+    fn date(s: &str) -> Date {
+        Date::parse(s).unwrap()
+    }
+
+    #[test]
+    fn dates_from_sources() {
+        let contents = "title: Shoulder press\ndate: 2019-12-11\n\nDid more than on 2019-12-01";
+        let find = |sources: &[&str]| {
+            let sources: Vec<DateSource> = sources.iter().map(|s| s.parse().unwrap()).collect();
+            let metadata = metadata_from_string(contents);
+            file_date("notes/20191210--shoulderpress.txt", contents, &metadata, &sources).map(|d| d.to_string())
+        };
+        assert_eq!(find(&["filename"]).as_deref(), Some("2019-12-10"));
+        assert_eq!(find(&["field"]).as_deref(), Some("2019-12-11"));
+        assert_eq!(find(&["field:created", "content"]).as_deref(), Some("2019-12-11"));
+        assert_eq!(find(&["field:created"]), None);
+        assert!("modified".parse::<DateSource>().is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        let range = DateRange {
+            since: Some(date("2024-01-01")),
+            until: None,
+        };
+        assert!(range.contains(date("2024-01-01")));
+        assert!(!range.contains(date("2023-12-31")));
+        assert!(DateRange::on(date("2024-02-29")).contains(date("2024-02-29")));
+        assert!(!DateRange::on(date("2024-02-29")).contains(date("2024-03-01")));
+        assert!(DateRange::default().is_open());
+    }
+}
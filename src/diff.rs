@@ -0,0 +1,221 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::Tag;
+use crate::filter::Filter;
+use crate::history::{blob_contents, git_error, open_repo, taggable_blobs};
+use crate::corpus::{Corpus, Keep};
+use crate::utility::{find_files, get_tags_from_string, relative_path, WalkOptions};
+
+/// The tags of every file in a directory or revision, keyed by relative path
+pub type Snapshot = Map<String, Set<Tag>>;
+
+/// Tags that changed between two snapshots
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct TagDiff {
+    /// Tags used in the second snapshot but not the first
+    pub added_tags: Vec<String>,
+    /// Tags used in the first snapshot but not the second
+    pub removed_tags: Vec<String>,
+    pub files: Vec<FileDiff>,
+    pub counts: Vec<CountDelta>,
+}
+
+/// Tags gained and lost by a single file
+#[derive(Debug, PartialEq, Serialize)]
+pub struct FileDiff {
+    pub path: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// A tag whose count (as from `Filter::count_of_tags`) changed
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CountDelta {
+    pub tag: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl CountDelta {
+    pub fn delta(&self) -> i64 {
+        self.after as i64 - self.before as i64
+    }
+}
+
+/// Take a snapshot of either a directory or, if `spec` doesn't exist, a
+/// revision of the git repository containing `repo_dir`
+///
+/// Also returns why any file that couldn't be read was skipped.
+pub fn snapshot(spec: &str, repo_dir: &str, options: WalkOptions) -> Result<(Snapshot, Vec<Error>), Error> {
+    let path = Path::new(spec);
+    if path.is_dir() {
+        snapshot_dir(spec, options)
+    } else if path.exists() {
+        Err(Error::new(ErrorKind::InvalidInput, format!("`{}` isn't a directory", spec)))
+    } else {
+        let snapshot = snapshot_revision(repo_dir, spec).map_err(|e| {
+            Error::new(
+                e.kind(),
+                format!("`{}` isn't a directory, or a revision in the git repository: {}", spec, e),
+            )
+        })?;
+        Ok((snapshot, Vec::new()))
+    }
+}
+
+/// Get the tags for every file under a directory, keyed by path within it
+///
+/// Files that can't be read are skipped, and returned with why.
+pub fn snapshot_dir(dir: &str, options: WalkOptions) -> Result<(Snapshot, Vec<Error>), Error> {
+    let corpus = Corpus::read(&find_files(dir, options)?, Keep::Tags);
+    let snapshot = corpus
+        .documents
+        .into_iter()
+        .map(|doc| (relative_path(&doc.path, dir), doc.tags))
+        .collect();
+    Ok((snapshot, corpus.unreadable))
+}
+
+/// Get the tags for every file in a git revision, keyed by path in the repo
+pub fn snapshot_revision(repo_dir: &str, rev: &str) -> Result<Snapshot, Error> {
+    let repo = open_repo(repo_dir)?;
+    let tree = repo
+        .rev_parse_single(rev)
+        .map_err(git_error)?
+        .object()
+        .map_err(git_error)?
+        .peel_to_tree()
+        .map_err(git_error)?;
+    let mut snapshot = Snapshot::new();
+    for (path, id) in taggable_blobs(&tree)? {
+        snapshot.insert(path, get_tags_from_string(&blob_contents(&repo, id)?));
+    }
+    Ok(snapshot)
+}
+
+fn joined(tags: &Set<Tag>) -> Set<String> {
+    tags.iter().map(|t| t.join("/")).collect()
+}
+
+/// Compare the tags in two snapshots
+pub fn diff(before: &Snapshot, after: &Snapshot) -> TagDiff {
+    let all_before: Set<String> = before.values().flat_map(joined).collect();
+    let all_after: Set<String> = after.values().flat_map(joined).collect();
+
+    let empty = Set::new();
+    let paths: Set<&String> = before.keys().chain(after.keys()).collect();
+    let files = paths
+        .into_iter()
+        .filter_map(|path| {
+            let old = joined(before.get(path).unwrap_or(&empty));
+            let new = joined(after.get(path).unwrap_or(&empty));
+            let added: Vec<String> = new.difference(&old).cloned().collect();
+            let removed: Vec<String> = old.difference(&new).cloned().collect();
+            if added.is_empty() && removed.is_empty() {
+                None
+            } else {
+                Some(FileDiff {
+                    path: path.to_string(),
+                    added,
+                    removed,
+                })
+            }
+        })
+        .collect();
+
+    let f = Filter::default();
+    let count = |snapshot: &Snapshot| -> Map<String, usize> {
+        let tagsets: Vec<Set<Tag>> = snapshot.values().cloned().collect();
+        f.count_of_tag_sets(&tagsets)
+            .into_iter()
+            .map(|(n, tag)| (tag, n))
+            .collect()
+    };
+    let (counts_before, counts_after) = (count(before), count(after));
+    let tags: Set<&String> = counts_before.keys().chain(counts_after.keys()).collect();
+    let mut counts: Vec<CountDelta> = tags
+        .into_iter()
+        .map(|tag| CountDelta {
+            tag: tag.to_string(),
+            before: counts_before.get(tag).copied().unwrap_or(0),
+            after: counts_after.get(tag).copied().unwrap_or(0),
+        })
+        .filter(|delta| delta.before != delta.after)
+        .collect();
+    counts.sort_by_key(|c| std::cmp::Reverse(c.delta().abs()));
+
+    TagDiff {
+        added_tags: all_after.difference(&all_before).cloned().collect(),
+        removed_tags: all_before.difference(&all_after).cloned().collect(),
+        files,
+        counts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(files: &[(&str, &str)]) -> Snapshot {
+        files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), get_tags_from_string(contents)))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_dotted_dirs() {
+        // Relative to the package, where tests are run from
+        let dir = format!("target/tagsearch-snapshot-{}", std::process::id());
+        std::fs::create_dir_all(format!("{}/sub", dir)).unwrap();
+        std::fs::write(format!("{}/sub/a.md", dir), "@rust").unwrap();
+        let dir_snapshot = |dir: &str| snapshot(dir, ".", WalkOptions::default()).unwrap().0;
+        let plain = dir_snapshot(&dir);
+        assert_eq!(plain.keys().collect::<Vec<_>>(), vec!["sub/a.md"]);
+        assert_eq!(dir_snapshot(&format!("./{}", dir)), plain);
+        assert_eq!(dir_snapshot(&format!("{}/", dir)), plain);
+
+        // Anything else that exists isn't taken as a revision
+        let err = snapshot(&format!("{}/sub/a.md", dir), ".", WalkOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("isn't a directory"));
+        let err = snapshot(&format!("{}/typo", dir), ".", WalkOptions::default()).unwrap_err();
+        assert!(err.to_string().contains("isn't a directory, or a revision"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_snapshots() {
+        let before = snap(&[("a.md", "@rust @old"), ("b.md", "@rust"), ("gone.md", "@old")]);
+        let after = snap(&[("a.md", "@rust @new/thing"), ("b.md", "@rust"), ("c.md", "@new")]);
+        let d = diff(&before, &after);
+        assert_eq!(d.added_tags, vec!["new", "new/thing"]);
+        assert_eq!(d.removed_tags, vec!["old"]);
+        assert_eq!(
+            d.files,
+            vec![
+                FileDiff {
+                    path: "a.md".to_string(),
+                    added: vec!["new/thing".to_string()],
+                    removed: vec!["old".to_string()],
+                },
+                FileDiff {
+                    path: "c.md".to_string(),
+                    added: vec!["new".to_string()],
+                    removed: vec![],
+                },
+                FileDiff {
+                    path: "gone.md".to_string(),
+                    added: vec![],
+                    removed: vec!["old".to_string()],
+                },
+            ]
+        );
+        let old = d.counts.iter().find(|c| c.tag == "old").unwrap();
+        assert_eq!((old.before, old.after, old.delta()), (2, 0, -2));
+        assert!(d.counts.iter().all(|c| c.tag != "rust"));
+    }
+}
@@ -1,9 +1,17 @@
-use crate::utility::get_tags_for_file;
+use crate::corpus::{Corpus, Document, Keep};
+use crate::dates::{file_date, sources_need_contents, sources_need_metadata, DateRange, DateSource};
+use crate::metadata::{metadata_from_string, Condition, Metadata};
+use crate::query::Query;
+use crate::similar::candidate_words;
+use crate::utility::{get_tags_for_file, get_tags_from_bytes, parse_heirarchical_tag, read_bytes};
 
+use std::borrow::Cow;
 use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::io::Error;
 
 use super::Tag;
 use rayon::prelude::*;
+use regex::Regex;
 
 /// The `Filter` struct is used for filtering files for tags
 ///
@@ -14,11 +22,32 @@ use rayon::prelude::*;
 /// and no bad words must exist. The filter can be made into an OR filter,
 /// where a file will be returned if ANY good word matches the file and NO
 /// bad words match.
+///
+/// Words that are comparisons, like `value>100` or `date>=2024-01-01`, are
+/// checked against the file's metadata (see `metadata_from_string`) rather
+/// than its tags.
+///
+/// A filter can also require a file's contents to match a pattern (see
+/// `with_pattern`).
 #[derive(Debug, Default)]
 pub struct Filter<'a> {
     good_keywords: Set<&'a str>,
     bad_keywords: Set<&'a str>,
+    /// Lowercase canonical forms of the keywords, which tags are checked
+    /// against (kept so they aren't rebuilt for every tag)
+    good_patterns: Vec<String>,
+    bad_patterns: Vec<String>,
+    good_conditions: Vec<Condition>,
+    bad_conditions: Vec<Condition>,
+    /// Keywords naming a saved query, with the filter a file must match to
+    /// satisfy them
+    good_queries: Vec<(&'a str, Filter<'a>)>,
+    bad_queries: Vec<(&'a str, Filter<'a>)>,
     or_filter: bool,
+    synonyms: Vec<Set<String>>,
+    aliases: Map<String, Tag>,
+    dates: Option<(DateRange, Vec<DateSource>)>,
+    pattern: Option<Regex>,
 }
 
 // TODO change issue to contain Tag instead of String
@@ -26,6 +55,41 @@ pub struct Filter<'a> {
 pub enum Issue {
     Plural(String, String),
     Case(String, String),
+    /// Differ only by `-` or `_`, e.g. `todo` and `to-do`
+    Separator(String, String),
+    /// A small edit apart, e.g. `pyhton` and `python`
+    Typo(String, String),
+    /// Listed as meaning the same thing, e.g. `ml` and `machine-learning`
+    Synonym(String, String),
+}
+
+impl Issue {
+    /// The two tags that look similar
+    pub fn tags(&self) -> (&str, &str) {
+        match self {
+            Issue::Plural(a, b)
+            | Issue::Case(a, b)
+            | Issue::Separator(a, b)
+            | Issue::Typo(a, b)
+            | Issue::Synonym(a, b) => (a, b),
+        }
+    }
+
+    /// How likely it is that the two tags were meant to be the same, from 0 to 1
+    pub fn confidence(&self) -> f64 {
+        match self {
+            Issue::Synonym(_, _) => 1.0,
+            Issue::Case(_, _) => 0.95,
+            Issue::Separator(_, _) => 0.9,
+            Issue::Plural(_, _) => 0.85,
+            Issue::Typo(a, b) => {
+                // Longer words are less likely to differ by a small edit by chance
+                let len = a.chars().count().max(b.chars().count()) as f64;
+                let distance = edit_distance(a, b) as f64;
+                (1.0 - distance / len).min(0.8)
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for Issue {
@@ -33,10 +97,200 @@ impl std::fmt::Display for Issue {
         match self {
             Issue::Plural(a, b) => write!(f, "Plural - {} & {}", a, b),
             Issue::Case(a, b) => write!(f, "Case - {} & {}", a, b),
+            Issue::Separator(a, b) => write!(f, "Separator - {} & {}", a, b),
+            Issue::Typo(a, b) => write!(f, "Typo - {} & {}", a, b),
+            Issue::Synonym(a, b) => write!(f, "Synonym - {} & {}", a, b),
         }
     }
 }
 
+/// Why a file did or didn't match a filter (see `Filter::explain`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    /// Each of the file's tags, with the keywords it matched
+    pub tags: Vec<TagExplanation>,
+    /// Each good keyword, and whether any tag satisfied it
+    pub keywords: Vec<(String, bool)>,
+    /// Each comparison, and whether the file's metadata meets it
+    pub conditions: Vec<ConditionExplanation>,
+    /// Each saved query used as a keyword, and whether the file matches it
+    pub queries: Vec<QueryExplanation>,
+    /// Whether the file's date is in the filter's range, if it has one
+    pub date: Option<bool>,
+    /// Whether the file's contents match the filter's pattern, if it has one
+    pub pattern: Option<bool>,
+    pub matches: bool,
+}
+
+/// The keywords that one of a file's tags matched
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagExplanation {
+    pub tag: Tag,
+    /// The tag with any aliases replaced, which is what keywords are checked against
+    pub canonical: Tag,
+    /// Good keywords the tag satisfied
+    pub satisfied: Vec<String>,
+    /// Bad keywords the tag matched, each of which rules the file out
+    pub violated: Vec<String>,
+}
+
+/// Whether a file's metadata meets one of a filter's comparisons
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionExplanation {
+    pub condition: Condition,
+    /// A bad comparison rules the file out when it holds
+    pub bad: bool,
+    pub holds: bool,
+}
+
+/// Whether a file matches one of the saved queries used by a filter
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryExplanation {
+    pub name: String,
+    /// A bad query rules the file out when it matches
+    pub bad: bool,
+    pub matches: bool,
+}
+
+/// The kind of difference between two components of a heirarchical tag
+#[derive(Debug, PartialEq)]
+enum Difference {
+    Same,
+    Case,
+    Separator,
+    Plural,
+    Typo,
+    Synonym,
+    Unrelated,
+}
+
+/// Irregular English plurals, as (singular, plural)
+const IRREGULAR_PLURALS: [(&str, &str); 12] = [
+    ("analysis", "analyses"),
+    ("child", "children"),
+    ("criterion", "criteria"),
+    ("datum", "data"),
+    ("foot", "feet"),
+    ("index", "indices"),
+    ("man", "men"),
+    ("mouse", "mice"),
+    ("person", "people"),
+    ("phenomenon", "phenomena"),
+    ("thesis", "theses"),
+    ("woman", "women"),
+];
+
+/// Possible singular forms of a word, using common English rules
+///
+/// A word that doesn't look plural is returned unchanged, as its own
+/// singular form.
+pub(crate) fn singular_forms(word: &str) -> Vec<String> {
+    let mut forms = vec![word.to_string()];
+    for (singular, plural) in IRREGULAR_PLURALS.iter() {
+        if let Some(stem) = word.strip_suffix(plural) {
+            forms.push(format!("{}{}", stem, singular));
+        }
+    }
+    if let Some(stem) = word.strip_suffix("ies") {
+        forms.push(format!("{}y", stem));
+    }
+    if let Some(stem) = word.strip_suffix("ves") {
+        forms.push(format!("{}f", stem));
+        forms.push(format!("{}fe", stem));
+    }
+    if let Some(stem) = word.strip_suffix("es") {
+        if ["s", "x", "z", "ch", "sh", "o"].iter().any(|end| stem.ends_with(end)) {
+            forms.push(stem.to_string());
+        }
+    }
+    if let Some(stem) = word.strip_suffix('s') {
+        if !stem.ends_with('s') && !stem.is_empty() {
+            forms.push(stem.to_string());
+        }
+    }
+    forms
+}
+
+fn is_plural_of(plural: &str, singular: &str) -> bool {
+    plural != singular && singular_forms(plural).iter().any(|form| form == singular)
+}
+
+/// Optimal string alignment distance, i.e. the number of insertions,
+/// deletions, substitutions, or swaps of adjacent characters to turn `a`
+/// into `b`
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The largest edit distance considered a typo for words of this length
+///
+/// Short words are too easily a small edit apart by chance (e.g. `cat` and
+/// `car`) to be flagged at all.
+pub(crate) fn max_typo_distance(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+fn strip_separators(word: &str) -> String {
+    word.chars().filter(|c| *c != '-' && *c != '_').collect()
+}
+
+/// Separate comparisons against metadata from plain keywords
+fn split_conditions<S: AsRef<str>>(keywords: &[S]) -> (Vec<Condition>, Set<&str>) {
+    let mut conditions = Vec::new();
+    let mut plain = Set::new();
+    for keyword in keywords {
+        match Condition::parse(keyword.as_ref()) {
+            Some(condition) => conditions.push(condition),
+            // Allow keywords to be written as tags, e.g. `@monthly`
+            None => {
+                plain.insert(keyword.as_ref().trim_start_matches('@'));
+            }
+        }
+    }
+    (conditions, plain)
+}
+
+/// Check if a lowercase canonical tag satisfies a lowercase canonical keyword
+///
+/// A keyword with a heirarchy separator must match whole components of the
+/// tag, in order, so `rust/async` matches `@lang/rust/async/tokio` but not
+/// `@rust/tokio` or `@lang/trust/asyncio`. Any other keyword is looked for
+/// in each component, so `rust` matches `@rust/async` and `@rustacean`
+/// alike.
+fn keyword_matches(keyword: &str, tag: &[String]) -> bool {
+    if keyword.contains('/') {
+        let parts: Vec<&str> = keyword.split('/').filter(|p| !p.is_empty()).collect();
+        parts.is_empty() || tag.windows(parts.len()).any(|run| run.iter().zip(&parts).all(|(t, p)| t == p))
+    } else {
+        tag.iter().any(|t| t.contains(keyword))
+    }
+}
+
 impl<'a> Filter<'a> {
     /// Create a new `Filter`
     ///
@@ -47,11 +301,158 @@ impl<'a> Filter<'a> {
         bad_keywords: &'a [S],
         or_filter: bool,
     ) -> Filter<'a> {
-        Filter {
-            good_keywords: keywords.iter().map(|x| x.as_ref()).collect(),
-            bad_keywords: bad_keywords.iter().map(|x| x.as_ref()).collect(),
+        let (good_conditions, keywords) = split_conditions(keywords);
+        let (bad_conditions, bad_keywords) = split_conditions(bad_keywords);
+        let mut filter = Filter {
+            good_keywords: keywords,
+            bad_keywords,
+            good_patterns: Vec::new(),
+            bad_patterns: Vec::new(),
+            good_conditions,
+            bad_conditions,
+            good_queries: Vec::new(),
+            bad_queries: Vec::new(),
             or_filter,
+            synonyms: Vec::new(),
+            aliases: Map::new(),
+            dates: None,
+            pattern: None,
+        };
+        filter.update_patterns();
+        filter
+    }
+
+    fn update_patterns(&mut self) {
+        self.good_patterns = self.good_keywords.iter().map(|k| self.canonical_keyword(k)).collect();
+        self.bad_patterns = self.bad_keywords.iter().map(|k| self.canonical_keyword(k)).collect();
+    }
+
+    /// Use groups of synonyms when looking for similar tags
+    ///
+    /// Each group is a list of words that mean the same thing, e.g.
+    /// `["ml", "machine-learning"]`. Words are compared ignoring case.
+    pub fn with_synonyms(mut self, groups: &[Vec<String>]) -> Filter<'a> {
+        self.synonyms = groups
+            .iter()
+            .map(|group| group.iter().map(|word| word.to_lowercase()).collect())
+            .collect();
+        self
+    }
+
+    /// Treat other spellings of a tag as the same tag
+    ///
+    /// `aliases` maps an alias to the canonical tag, e.g. `ml` to
+    /// `machine-learning`. Any component of a heirarchical tag (or query
+    /// keyword) that is exactly an alias, ignoring case, is replaced by the
+    /// canonical tag, which may itself be heirarchical (e.g. `js` to
+    /// `programming/javascript`).
+    pub fn with_aliases(mut self, aliases: &Map<String, String>) -> Filter<'a> {
+        self.aliases = aliases
+            .iter()
+            .map(|(alias, canonical)| (alias.to_lowercase(), parse_heirarchical_tag(canonical)))
+            .collect();
+        self.update_patterns();
+        self
+    }
+
+    /// Treat keywords that name a saved query as virtual tags
+    ///
+    /// A file satisfies such a keyword (e.g. `reading`, or `@reading`) by
+    /// matching the saved query, rather than by having a tag containing the
+    /// word. Saved queries can use each other, but a query that ends up
+    /// using itself treats its own name as a plain keyword. Aliases must be
+    /// given (with `with_aliases`) before this, to be used by saved queries.
+    pub fn with_queries(self, queries: &'a Map<String, Query>) -> Filter<'a> {
+        self.with_queries_except(queries, &[])
+    }
+
+    fn with_queries_except(mut self, queries: &'a Map<String, Query>, using: &[&str]) -> Filter<'a> {
+        let aliases = &self.aliases;
+        let saved = |keywords: &Set<&'a str>| -> Vec<(&'a str, Filter<'a>)> {
+            keywords
+                .iter()
+                .filter(|k| !using.contains(k))
+                .filter_map(|&k| {
+                    let query = queries.get(k)?;
+                    let mut f = Filter::new(&query.good, &query.bad, query.or);
+                    f.aliases = aliases.clone();
+                    f.update_patterns();
+                    let using: Vec<&str> = using.iter().copied().chain(Some(k)).collect();
+                    Some((k, f.with_queries_except(queries, &using)))
+                })
+                .collect()
+        };
+        self.good_queries = saved(&self.good_keywords);
+        self.bad_queries = saved(&self.bad_keywords);
+        for (name, _) in &self.good_queries {
+            self.good_keywords.remove(name);
         }
+        for (name, _) in &self.bad_queries {
+            self.bad_keywords.remove(name);
+        }
+        self.update_patterns();
+        self
+    }
+
+    /// Only match files with a date in `range`, taken from the first of
+    /// `sources` that gives one
+    ///
+    /// An open range doesn't restrict anything.
+    pub fn with_dates(mut self, range: DateRange, sources: &[DateSource]) -> Filter<'a> {
+        self.dates = if range.is_open() {
+            None
+        } else {
+            Some((range, sources.to_vec()))
+        };
+        self
+    }
+
+    /// Only match files whose contents match `pattern` somewhere
+    pub fn with_pattern(mut self, pattern: Regex) -> Filter<'a> {
+        self.pattern = Some(pattern);
+        self
+    }
+
+    /// The pattern file contents must match, if any
+    pub fn pattern(&self) -> Option<&Regex> {
+        self.pattern.as_ref()
+    }
+
+    /// Check if a file's date is within the filter's date range (if any)
+    pub fn date_matches(&self, filename: &str, contents: &str, metadata: &Metadata) -> bool {
+        match &self.dates {
+            Some((range, sources)) => {
+                file_date(filename, contents, metadata, sources).is_some_and(|date| range.contains(date))
+            }
+            None => true,
+        }
+    }
+
+    /// The canonical form of a tag, with every alias replaced
+    pub fn canonical_tag(&self, tag: &Tag) -> Tag {
+        tag.iter()
+            .flat_map(|word| match self.aliases.get(&word.to_lowercase()) {
+                Some(canonical) => canonical.clone(),
+                None => vec![word.to_string()],
+            })
+            .collect()
+    }
+
+    /// The canonical form of a set of tags (borrowed, if there are no aliases)
+    pub fn canonical_tags<'t>(&self, tags: &'t Set<Tag>) -> Cow<'t, Set<Tag>> {
+        if self.aliases.is_empty() {
+            Cow::Borrowed(tags)
+        } else {
+            Cow::Owned(tags.iter().map(|t| self.canonical_tag(t)).collect())
+        }
+    }
+
+    /// Tags that were written using an alias, along with their canonical form
+    pub fn aliases_used(&self, tags: &Set<Tag>) -> Vec<(Tag, Tag)> {
+        tags.iter()
+            .map(|tag| (tag.clone(), self.canonical_tag(tag)))
+            .filter(|(tag, canonical)| tag != canonical)
+            .collect()
     }
 
     /// Check if a set of tags matches the filter
@@ -59,42 +460,260 @@ impl<'a> Filter<'a> {
     /// This takes a bunch of tags that have been pulled from a file, and
     /// checks if the good and bad keywords match.
     ///
-    /// TODO probably want to use a set here to make sure I'm matching all terms
-    /// rather than just asuming that if I have more matches than good keywords that it's good.
-    /// ...if so, how do I handle heirarchical tags?
-    /// ...only look for the sub-words of the heirarchy, or count it as both?
+    /// Each keyword is satisfied (or not) by the file as a whole, so an AND
+    /// filter needs every good keyword to be satisfied by at least one tag,
+    /// however many times other keywords match; `rust python` doesn't match
+    /// a file tagged `@rust/async` and `@rust/tokio`. One tag can satisfy
+    /// several keywords, e.g. `@rust/async` satisfies both `rust` and
+    /// `async`. See `keyword_matches` for how a keyword is checked against a
+    /// heirarchical tag. A filter with no good keywords (or comparisons)
+    /// matches any file without a bad keyword.
+    ///
+    /// Any comparisons in the filter are checked against no metadata, so
+    /// never match; use `matches_with_metadata` for those.
     pub fn matches(&self, tags: &Set<Tag>) -> bool {
-        let mut num_matching_tags: usize = 0;
-        // Here 'tags' is what we've pulled from the file
-        for heirarchicaltag in tags {
-            for tag in heirarchicaltag {
-                let tag_l = tag.to_lowercase();
-                if self.tag_matches(&self.bad_keywords, &tag_l) {
-                    return false;
-                }
-                if self.tag_matches(&self.good_keywords, &tag_l) {
-                    num_matching_tags += 1;
+        self.matches_with_metadata(tags, &Metadata::new())
+    }
+
+    /// Check if a file's tags and metadata match the filter
+    ///
+    /// Each comparison counts as one keyword, so an AND filter needs all of
+    /// them to hold, and an OR filter can be satisfied by any one.
+    pub fn matches_with_metadata(&self, original_tags: &Set<Tag>, metadata: &Metadata) -> bool {
+        if self.bad_conditions.iter().any(|c| c.matches(metadata)) {
+            return false;
+        }
+        let tags = self.lowercase_tags(original_tags);
+        if self.bad_patterns.iter().any(|k| tags.iter().any(|t| keyword_matches(k, t))) {
+            return false;
+        }
+        let saved_matches = |(_, f): &(&str, Filter)| f.matches_with_metadata(original_tags, metadata);
+        if self.bad_queries.iter().any(saved_matches) {
+            return false;
+        }
+        let queries_met = self.good_queries.iter().filter(|q| saved_matches(q)).count();
+        let conditions_met = self.good_conditions.iter().filter(|c| c.matches(metadata)).count();
+        self.enough_satisfied(self.satisfied(&tags).len() + queries_met, conditions_met)
+    }
+
+    /// Whether satisfying this many good keywords (including saved queries)
+    /// and comparisons is enough to match, for a file without any bad ones
+    fn enough_satisfied(&self, keywords_met: usize, conditions_met: usize) -> bool {
+        let keywords = self.good_patterns.len() + self.good_queries.len();
+        if self.or_filter {
+            keywords + self.good_conditions.len() == 0 || keywords_met + conditions_met > 0
+        } else {
+            keywords_met == keywords && conditions_met == self.good_conditions.len()
+        }
+    }
+
+    /// Check if a file's tags and metadata match the filter, recording why
+    ///
+    /// This makes the same decision as `matches_with_metadata`, but checks
+    /// every tag against every keyword, rather than stopping once the
+    /// decision is known.
+    pub fn explain(&self, original_tags: &Set<Tag>, metadata: &Metadata) -> Explanation {
+        let tags: Vec<TagExplanation> = original_tags
+            .iter()
+            .map(|tag| {
+                let lower = self.lowercase_tag(tag);
+                let matched = |keywords: &Set<&str>, patterns: &[String]| -> Vec<String> {
+                    keywords
+                        .iter()
+                        .zip(patterns)
+                        .filter(|(_, pattern)| keyword_matches(pattern, &lower))
+                        .map(|(keyword, _)| keyword.to_string())
+                        .collect()
+                };
+                TagExplanation {
+                    tag: tag.clone(),
+                    canonical: self.canonical_tag(tag),
+                    satisfied: matched(&self.good_keywords, &self.good_patterns),
+                    violated: matched(&self.bad_keywords, &self.bad_patterns),
                 }
-            }
-            if self.tag_matches(
-                &self.good_keywords,
-                &heirarchicaltag.join("/").to_lowercase(),
-            ) {
-                num_matching_tags += 1;
-            }
+            })
+            .collect();
+        let keywords: Vec<(String, bool)> = self
+            .good_keywords
+            .iter()
+            .map(|k| (k.to_string(), tags.iter().any(|t| t.satisfied.iter().any(|s| s == k))))
+            .collect();
+        let conditions: Vec<ConditionExplanation> = self
+            .good_conditions
+            .iter()
+            .map(|c| (c, false))
+            .chain(self.bad_conditions.iter().map(|c| (c, true)))
+            .map(|(c, bad)| ConditionExplanation {
+                condition: c.clone(),
+                bad,
+                holds: c.matches(metadata),
+            })
+            .collect();
+        let queries: Vec<QueryExplanation> = self
+            .good_queries
+            .iter()
+            .map(|q| (q, false))
+            .chain(self.bad_queries.iter().map(|q| (q, true)))
+            .map(|((name, f), bad)| QueryExplanation {
+                name: name.to_string(),
+                bad,
+                matches: f.matches_with_metadata(original_tags, metadata),
+            })
+            .collect();
+        let excluded = tags.iter().any(|t| !t.violated.is_empty())
+            || conditions.iter().any(|c| c.bad && c.holds)
+            || queries.iter().any(|q| q.bad && q.matches);
+        let keywords_met = keywords.iter().filter(|(_, satisfied)| *satisfied).count()
+            + queries.iter().filter(|q| !q.bad && q.matches).count();
+        let conditions_met = conditions.iter().filter(|c| !c.bad && c.holds).count();
+        Explanation {
+            matches: !excluded && self.enough_satisfied(keywords_met, conditions_met),
+            tags,
+            keywords,
+            conditions,
+            queries,
+            date: None,
+            pattern: None,
+        }
+    }
+
+    /// Check if an already-read file matches the filter, recording why
+    ///
+    /// Unlike `explain`, this also checks the file's date and contents, as
+    /// `document_matches` does.
+    pub fn explain_document(&self, doc: &Document) -> Explanation {
+        let mut explanation = self.explain(&doc.tags, &doc.metadata);
+        explanation.date = self.dates.as_ref().map(|_| self.date_matches(&doc.path, doc.contents(), &doc.metadata));
+        explanation.pattern = self.pattern.as_ref().map(|p| p.is_match(doc.contents()));
+        explanation.matches &= explanation.date != Some(false) && explanation.pattern != Some(false);
+        explanation
+    }
+
+    /// The good keywords (not comparisons) that a set of tags satisfies
+    pub fn satisfied_keywords(&self, tags: &Set<Tag>) -> Set<&'a str> {
+        let satisfied = self.satisfied(&self.lowercase_tags(tags));
+        self.good_keywords
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| satisfied.contains(i))
+            .map(|(_, k)| *k)
+            .collect()
+    }
+
+    /// Indices of the good patterns satisfied by any of `tags`
+    fn satisfied(&self, tags: &[Tag]) -> Set<usize> {
+        self.good_patterns
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| tags.iter().any(|t| keyword_matches(k, t)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn lowercase_tags(&self, tags: &Set<Tag>) -> Vec<Tag> {
+        tags.iter().map(|t| self.lowercase_tag(t)).collect()
+    }
+
+    /// Whether the filter needs to know files' metadata, for a comparison or
+    /// a date
+    pub fn uses_metadata(&self) -> bool {
+        !(self.good_conditions.is_empty() && self.bad_conditions.is_empty())
+            || self.dates.as_ref().is_some_and(|(_, sources)| sources_need_metadata(sources))
+            || self.good_queries.iter().chain(&self.bad_queries).any(|(_, f)| f.uses_metadata())
+    }
+
+    /// Whether the filter needs files' text, rather than only their tags and
+    /// metadata
+    pub fn needs_contents(&self) -> bool {
+        self.pattern.is_some()
+            || self.dates.as_ref().is_some_and(|(_, sources)| sources_need_contents(sources))
+            || self.good_queries.iter().chain(&self.bad_queries).any(|(_, f)| f.needs_contents())
+    }
+
+    /// How much of each file checking it against the filter needs
+    pub fn keep(&self) -> Keep {
+        if self.needs_contents() {
+            Keep::Text
+        } else if self.uses_metadata() {
+            Keep::Metadata
+        } else {
+            Keep::Tags
         }
-        let matches_required = if self.or_filter {
-            1 // If we are using an or filter, any 1 match is good enough
+    }
+
+    /// Read a file's tags, and check if the whole file matches the filter
+    ///
+    /// Unlike `matches`, this also checks the file's metadata, date, and
+    /// contents.
+    fn read_file(&self, filename: &str) -> (Set<Tag>, bool) {
+        let bytes = read_bytes(filename)
+            .unwrap_or_else(|_| panic!("Couldn't read contents of file: `{:?}`", filename));
+        let contents = String::from_utf8_lossy(&bytes);
+        let metadata = if self.uses_metadata() {
+            metadata_from_string(&contents)
         } else {
-            self.good_keywords.len() // Otherwise, we must match all keywords
+            Metadata::new()
         };
-        num_matching_tags >= matches_required
+        let tags = get_tags_from_bytes(&bytes);
+        let matches = self.file_matches(filename, &contents, &tags, &metadata);
+        (tags, matches)
+    }
+
+    fn file_matches(&self, filename: &str, contents: &str, tags: &Set<Tag>, metadata: &Metadata) -> bool {
+        self.date_matches(filename, contents, metadata)
+            && self.pattern.as_ref().is_none_or(|p| p.is_match(contents))
+            && self.matches_with_metadata(tags, metadata)
     }
 
-    #[inline(always)]
-    fn tag_matches(&self, v: &Set<&str>, t: &str) -> bool {
-        v.iter()
-            .any(|haystack| t.contains(&haystack.to_lowercase()))
+    /// Check if an already-read file matches the filter, including its
+    /// metadata, date, and contents
+    pub fn document_matches(&self, doc: &Document) -> bool {
+        self.file_matches(&doc.path, doc.contents(), &doc.tags, &doc.metadata)
+    }
+
+    /// Check if a single tag satisfies any of the good keywords
+    ///
+    /// This uses the same rules as `matches` (see `keyword_matches`), and a
+    /// tag wanted by a saved query is wanted by any filter using it. Bad
+    /// keywords are ignored.
+    pub fn wants_tag(&self, tag: &Tag) -> bool {
+        if self.good_queries.iter().any(|(_, f)| f.wants_tag(tag)) {
+            return true;
+        }
+        if self.good_patterns.is_empty() {
+            return false;
+        }
+        let tag = self.lowercase_tag(tag);
+        self.good_patterns.iter().any(|k| keyword_matches(k, &tag))
+    }
+
+    /// The good keywords (not comparisons) in the filter
+    pub fn keywords(&self) -> Vec<&str> {
+        self.good_keywords.iter().copied().collect()
+    }
+
+    /// Check if a single tag satisfies one particular keyword, using the same
+    /// rules as `wants_tag`
+    pub fn keyword_wants_tag(&self, keyword: &str, tag: &Tag) -> bool {
+        keyword_matches(&self.canonical_keyword(keyword), &self.lowercase_tag(tag))
+    }
+
+    /// The canonical form of a tag, in lowercase to compare to keywords
+    fn lowercase_tag(&self, tag: &Tag) -> Tag {
+        let tag = if self.aliases.is_empty() {
+            Cow::Borrowed(tag)
+        } else {
+            Cow::Owned(self.canonical_tag(tag))
+        };
+        tag.iter().map(|t| t.to_lowercase()).collect()
+    }
+
+    /// A lowercase query keyword, with any aliases replaced by their
+    /// canonical tag, and heirarchy separators written as `/`
+    fn canonical_keyword(&self, keyword: &str) -> String {
+        self.canonical_tag(&parse_heirarchical_tag(keyword))
+            .join("/")
+            .to_lowercase()
     }
 
     /// Extract ALL tags from files that match a filter
@@ -104,15 +723,21 @@ impl<'a> Filter<'a> {
     pub fn tags_matching_tag_query(&self, files: &[String]) -> Set<Tag> {
         files
             .par_iter()
-            .map(|x| get_tags_for_file(x))
-            .filter(|x| {
-                if !(self.good_keywords.is_empty() || self.bad_keywords.is_empty()) {
-                    self.matches(x)
-                } else {
-                    true
-                }
-            })
-            .flatten()
+            .map(|x| self.read_file(x))
+            .filter(|(_, matches)| *matches)
+            .flat_map(|(tags, _)| self.canonical_tags(&tags).into_owned())
+            .collect()
+    }
+
+    /// Extract ALL tags from already-read files that match a filter
+    ///
+    /// This is the same as `tags_matching_tag_query`, without reading files.
+    pub fn tags_matching_in(&self, corpus: &Corpus) -> Set<Tag> {
+        corpus
+            .documents
+            .par_iter()
+            .filter(|doc| self.document_matches(doc))
+            .flat_map(|doc| self.canonical_tags(&doc.tags).into_owned())
             .collect()
     }
 
@@ -122,93 +747,174 @@ impl<'a> Filter<'a> {
     pub fn files_matching_tag_query(&self, files: &[String]) -> Vec<String> {
         files
             .par_iter()
-            .filter(|fname| self.matches(&get_tags_for_file(fname)))
+            .filter(|fname| self.read_file(fname).1)
             .map(|fname| fname.to_string())
             .collect::<Vec<String>>()
     }
 
-    /// Get all files without tags
-    pub fn untagged_files(&self, files: &[String]) -> Vec<String> {
-        files
+    /// Already-read files that match a filter, in the order they were read
+    pub fn documents_matching<'d>(&self, corpus: &'d Corpus) -> Vec<&'d Document> {
+        corpus
+            .documents
             .par_iter()
-            .filter(|x| get_tags_for_file(x).is_empty())
-            .map(|x| x.to_string())
+            .filter(|doc| self.document_matches(doc))
             .collect()
     }
 
+    /// Get all files without tags
+    pub fn untagged_files(&self, files: &[String]) -> Result<Vec<String>, Error> {
+        let untagged: Vec<Option<String>> = files
+            .par_iter()
+            .map(|x| Ok(get_tags_for_file(x)?.is_empty().then(|| x.to_string())))
+            .collect::<Result<_, Error>>()?;
+        Ok(untagged.into_iter().flatten().collect())
+    }
+
     /// List possibly similar tags, based on some simple heuristics.
     ///
-    /// This only does a simple test for case (i.e. upper vs lowercase) and
-    /// plurality. The plurality check only does a simple test if one tag ends
-    /// with 's' and the other doesn't.
+    /// Tags are compared one component of the heirarchy at a time. Two tags
+    /// are similar if at least one component differs only by case, by `-` or
+    /// `_` separators, by being an English plural, by a small typo (edit
+    /// distance), or by being listed as synonyms, and no component is
+    /// otherwise different.
     ///
     /// If the pair (A,B) is listed as having a problem, the pair (B,A) WILL
     /// NOT be added to the result.
-    pub fn similar_tags(&self, files: &[String]) -> Vec<Issue> {
-        let mut tagset: Set<Tag> = Set::new();
-        files.iter().for_each(|entry| {
-            tagset.extend(get_tags_for_file(entry));
-        });
-        let mut similar = Vec::new();
-        for ts1 in &tagset {
-            for ts2 in &tagset {
-                if let Some(issue) = Filter::compare_heirarchical_tags(ts1, ts2) {
-                    if !similar.contains(&issue) {
-                        similar.push(issue);
-                    }
-                }
+    pub fn similar_tags(&self, files: &[String]) -> Result<Vec<Issue>, Error> {
+        let tagsets: Vec<Set<Tag>> = files
+            .par_iter()
+            .map(|entry| get_tags_for_file(entry))
+            .collect::<Result<_, Error>>()?;
+        Ok(self.similar_tags_in(&tagsets.into_iter().flatten().collect()))
+    }
+
+    /// List possibly similar tags from an already-gathered set of tags
+    ///
+    /// Rather than comparing every pair of tags, this first finds candidate
+    /// pairs of words (see `similar::candidate_words`), then only compares
+    /// tags that have one of those words in the same position of the
+    /// heirarchy. Results are in the same order as comparing every pair.
+    pub fn similar_tags_in(&self, tagset: &Set<Tag>) -> Vec<Issue> {
+        // Aliases are known to be the same tag, so aren't worth reporting
+        let tagset = self.canonical_tags(tagset);
+        let tags: Vec<&Tag> = tagset.iter().collect();
+        let words: Set<&str> = tags.iter().flat_map(|t| t.iter().map(|w| w.as_str())).collect();
+        let candidates = candidate_words(&words, &self.synonyms);
+
+        // Which tags (by index) have each word at each depth
+        let mut positions: Map<(usize, &str), Vec<usize>> = Map::new();
+        for (i, tag) in tags.iter().enumerate() {
+            for (depth, word) in tag.iter().enumerate() {
+                positions.entry((depth, word)).or_default().push(i);
             }
         }
-        similar
+
+        tags.par_iter()
+            .enumerate()
+            .flat_map_iter(|(i, ts1)| {
+                let mut others: Set<usize> = Set::new();
+                for (depth, word) in ts1.iter().enumerate() {
+                    for similar in candidates.get(word.as_str()).into_iter().flatten() {
+                        if let Some(found) = positions.get(&(depth, similar.as_str())) {
+                            others.extend(found.iter().filter(|j| **j > i));
+                        }
+                    }
+                }
+                others
+                    .into_iter()
+                    .filter_map(|j| self.compare_heirarchical_tags(ts1, tags[j]))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
     }
 
-    fn compare_heirarchical_tags(t1: &Tag, t2: &Tag) -> Option<Issue> {
+    fn compare_heirarchical_tags(&self, t1: &Tag, t2: &Tag) -> Option<Issue> {
         // Compare each component of the heirarchy
         // rather than treating it as a single string
+        let mut first_difference = None;
         for (key, key2) in t1.iter().zip(t2.iter()) {
-            if key == key2 {
-                continue;
-            }
-            if key.to_lowercase() == key2.to_lowercase() {
-                // Ensure we don't add B-A if we've flagged A-B
-                return Some(Issue::Case(t1.join("/").to_string(), t2.join("/").to_string()));
-            } else if key.trim_end_matches('s') == key2.trim_end_matches('s') {
-                // Ensure we don't add B-A if we've flagged A-B
-                return Some(Issue::Plural(t1.join("/").to_string(), t2.join("/").to_string()));
+            match self.compare_words(key, key2) {
+                Difference::Same => continue,
+                Difference::Unrelated => return None,
+                difference => {
+                    first_difference.get_or_insert(difference);
+                }
             }
         }
-        None
+        let (a, b) = (t1.join("/"), t2.join("/"));
+        match first_difference? {
+            Difference::Case => Some(Issue::Case(a, b)),
+            Difference::Separator => Some(Issue::Separator(a, b)),
+            Difference::Plural => Some(Issue::Plural(a, b)),
+            Difference::Typo => Some(Issue::Typo(a, b)),
+            Difference::Synonym => Some(Issue::Synonym(a, b)),
+            Difference::Same | Difference::Unrelated => None,
+        }
+    }
+
+    fn compare_words(&self, key: &str, key2: &str) -> Difference {
+        if key == key2 {
+            return Difference::Same;
+        }
+        let (lower, lower2) = (key.to_lowercase(), key2.to_lowercase());
+        if lower == lower2 {
+            Difference::Case
+        } else if strip_separators(key) == strip_separators(key2) {
+            Difference::Separator
+        } else if is_plural_of(key, key2) || is_plural_of(key2, key) {
+            Difference::Plural
+        } else if self
+            .synonyms
+            .iter()
+            .any(|group| group.contains(&lower) && group.contains(&lower2))
+        {
+            Difference::Synonym
+        } else if !(key.chars().any(|c| c.is_ascii_digit()) && key2.chars().any(|c| c.is_ascii_digit()))
+            && edit_distance(&lower, &lower2)
+                <= max_typo_distance(lower.chars().count().min(lower2.chars().count()))
+        {
+            // Numbers (e.g. years) are often only an edit apart, so aren't typos
+            Difference::Typo
+        } else {
+            Difference::Unrelated
+        }
     }
 
     /// Count the number of occurences of each tag
     ///
     /// This will count how many files each tag appears in. The returned
     /// vector is sorted high to low.
-    pub fn count_of_tags(&self, files: &[String]) -> Vec<(usize, String)> {
-        let mut tagmap: Map<String, usize> = Map::new();
-        for entry in files {
-            for tag in get_tags_for_file(entry) {
-                for subtag in &tag {
-                    match tagmap.get_mut(subtag) {
-                        Some(val) => *val += 1,
-                        None => {
-                            tagmap.insert(subtag.to_string(), 1);
-                        }
-                    }
-                }
+    pub fn count_of_tags(&self, files: &[String]) -> Result<Vec<(usize, String)>, Error> {
+        let tagsets: Vec<Set<Tag>> = files
+            .par_iter()
+            .map(|f| get_tags_for_file(f))
+            .collect::<Result<_, Error>>()?;
+        Ok(self.count_of_tag_sets(&tagsets))
+    }
 
+    /// Count the number of occurences of each tag, from already-parsed tags
+    ///
+    /// This is the same as `count_of_tags`, but takes the tags of each file
+    /// rather than reading them, e.g. for tags from a git revision or a
+    /// `Corpus`.
+    pub fn count_of_tag_sets<'t, I>(&self, tagsets: I) -> Vec<(usize, String)>
+    where
+        I: IntoIterator<Item = &'t Set<Tag>>,
+    {
+        // Count borrowed components, only allocating for full heirarchical tags
+        let tagsets: Vec<Cow<Set<Tag>>> = tagsets.into_iter().map(|tags| self.canonical_tags(tags)).collect();
+        let mut tagmap: Map<Cow<str>, usize> = Map::new();
+        for tags in &tagsets {
+            for tag in tags.iter() {
+                for subtag in tag {
+                    *tagmap.entry(Cow::Borrowed(subtag.as_str())).or_insert(0) += 1;
+                }
                 if tag.len() > 1 {
-                    let fulltag = tag.join("/");
-                    match tagmap.get_mut(&fulltag) {
-                        Some(val) => *val += 1,
-                        None => {
-                            tagmap.insert(fulltag, 1);
-                        }
-                    }
+                    *tagmap.entry(Cow::Owned(tag.join("/"))).or_insert(0) += 1;
                 }
             }
         }
-        let mut out: Vec<_> = tagmap.iter().map(|(k, v)| (*v, k.clone())).collect();
+        let mut out: Vec<_> = tagmap.into_iter().map(|(k, v)| (v, k.into_owned())).collect();
         out.sort_by(|a, b| a.0.cmp(&b.0).reverse());
         out
     }
@@ -218,8 +924,13 @@ impl<'a> Filter<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::metadata::Date;
     use crate::utility::parse_heirarchical_tag as tagparse;
 
+    fn tags(ts: &[&str]) -> Set<Tag> {
+        ts.iter().map(|t| tagparse(t)).collect()
+    }
+
     /// This macro just streamlines the repetitive filter creation and set creation.
     macro_rules! tag_match {
         (good [$($good:literal),*] bad [$($bad:literal),*] file_tags [$($filetags:literal),+] $case:literal $negate:literal) => {
@@ -250,17 +961,22 @@ mod tests {
     macro_rules! tag_compare {
         (plural $first:literal is like $second:literal) => {
             assert_eq!(
-                Filter::compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
+                Filter::default().compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
                 Some(Issue::Plural($first.to_string(), $second.to_string())));
         };
         (lowercase $first:literal is like lowercase $second:literal) => {
             assert_eq!(
-                Filter::compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
+                Filter::default().compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
                 Some(Issue::Case($first.to_string(), $second.to_string())));
         };
+        ($kind:ident $first:literal is like $second:literal) => {
+            assert_eq!(
+                Filter::default().compare_heirarchical_tags(&tagparse($first), &tagparse($second)),
+                Some(Issue::$kind($first.to_string(), $second.to_string())));
+        };
         ($first:literal is not like $second:literal) => {
             assert_eq!(
-                Filter::compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
+                Filter::default().compare_heirarchical_tags(&tagparse($first), &tagparse($second)), 
                 None)
         }
     }
@@ -280,6 +996,69 @@ mod tests {
         tag_match!(["donkey"] rejects ["stoicism", "philosophy", "donkey"]);
     }
 
+    #[test]
+    fn match_every_keyword() {
+        let rusty = tags(&["rust/async", "rust/tokio"]);
+
+        // Matching one keyword many times doesn't make up for another
+        assert!(!Filter::new(&["rust", "python"], &[], false).matches(&rusty));
+        assert!(!Filter::new(&["rust", "python", "async"], &[], false).matches(&rusty));
+        assert!(Filter::new(&["rust", "python"], &[], true).matches(&rusty));
+        // ...but one tag can satisfy several keywords
+        assert!(Filter::new(&["rust", "async"], &[], false).matches(&tags(&["rust/async"])));
+        assert!(Filter::new(&["rust", "RUST", "@rust"], &[], false).matches(&tags(&["rust"])));
+
+        let f = Filter::new(&["rust", "python"], &[], false);
+        let both = tags(&["rust/async", "lang/python"]);
+        assert!(f.matches(&both));
+        assert_eq!(f.satisfied_keywords(&rusty), ["rust"].iter().copied().collect());
+        assert_eq!(f.satisfied_keywords(&both).len(), 2);
+
+        // Comparisons must still all hold too
+        let f = Filter::new(&["rust", "value>1"], &[], false);
+        assert!(!f.matches_with_metadata(&rusty, &metadata_from_string("value: 0")));
+        assert!(f.matches_with_metadata(&rusty, &metadata_from_string("value: 2")));
+        let f = Filter::new(&["python", "value>1"], &[], true);
+        assert!(f.matches_with_metadata(&rusty, &metadata_from_string("value: 2")));
+
+        // A query with nothing to satisfy matches anything not excluded
+        assert!(Filter::new::<&str>(&[], &[], false).matches(&rusty));
+        assert!(Filter::new::<&str>(&[], &[], true).matches(&rusty));
+        assert!(!Filter::new(&[], &["tokio"], true).matches(&rusty));
+    }
+
+    #[test]
+    fn match_heirarchy() {
+        let rusty = tags(&["lang/rust/async", "rust/tokio"]);
+
+        // Keywords without a separator are looked for in each component
+        assert!(Filter::new(&["rust", "tok"], &[], false).matches(&rusty));
+        assert!(!Filter::new(&["rust/t"], &[], false).matches(&tags(&["rust", "tokio"])));
+        // ...and keywords with one as whole components, however it's written
+        assert!(Filter::new(&["rust/async"], &[], false).matches(&rusty));
+        assert!(Filter::new(&["Rust:Tokio"], &[], false).matches(&rusty));
+        assert!(!Filter::new(&["rust/async"], &[], false).matches(&tags(&["lang/trust/asyncio"])));
+        assert!(!Filter::new(&["ust/tok"], &[], false).matches(&rusty));
+        assert!(!Filter::new(&["rust/async", "python"], &[], false).matches(&rusty));
+        assert!(!Filter::new(&["async/rust"], &[], false).matches(&rusty));
+
+        // Bad keywords follow the same rules
+        assert!(!Filter::new(&["rust"], &["rust/tokio"], false).matches(&rusty));
+        assert!(Filter::new(&["rust"], &["python/tokio"], false).matches(&rusty));
+        assert!(!Filter::new(&["rust"], &["async"], false).matches(&rusty));
+    }
+
+    #[test]
+    fn wants_tag() {
+        let f = Filter::new(&["rust", "lang/c"], &["video"], false);
+        assert!(f.wants_tag(&tagparse("rust")));
+        assert!(f.wants_tag(&tagparse("programming/rust/async")));
+        assert!(f.wants_tag(&tagparse("lang/c")));
+        assert!(!f.wants_tag(&tagparse("lang/cpp")));
+        assert!(!f.wants_tag(&tagparse("python")));
+        assert!(!f.wants_tag(&tagparse("video")));
+    }
+
     #[test]
     fn compare_tags(){
         tag_compare!(plural "as" is like "a");
@@ -290,4 +1069,210 @@ mod tests {
         tag_compare!(lowercase "a/B/c" is like lowercase "a/b/c");
         tag_compare!("As" is not like "a");
     }
+
+    #[test]
+    fn compare_tags_further() {
+        tag_compare!(plural "categories" is like "category");
+        tag_compare!(plural "boxes" is like "box");
+        tag_compare!(plural "people" is like "person");
+        tag_compare!(plural "notes/leaves" is like "notes/leaf");
+        tag_compare!(Separator "todo" is like "to-do");
+        tag_compare!(Separator "machine_learning" is like "machine-learning");
+        tag_compare!(Typo "pyhton" is like "python");
+        tag_compare!(Typo "analyse" is like "analyze");
+        tag_compare!(Typo "lang/pyhton" is like "lang/python");
+        tag_compare!("rust/async" is not like "python/async");
+        tag_compare!("cat" is not like "car");
+        tag_compare!("2023" is not like "2024");
+        tag_compare!("ml" is not like "machine-learning");
+
+        let synonyms = vec![vec!["ML".to_string(), "machine-learning".to_string()]];
+        let f = Filter::default().with_synonyms(&synonyms);
+        assert_eq!(
+            f.compare_heirarchical_tags(&tagparse("ai/ml"), &tagparse("ai/machine-learning")),
+            Some(Issue::Synonym("ai/ml".to_string(), "ai/machine-learning".to_string()))
+        );
+    }
+
+    #[test]
+    fn typo_confidence() {
+        let close = Issue::Typo("pyhton".to_string(), "python".to_string());
+        let far = Issue::Typo("scripting".to_string(), "scirpitng".to_string());
+        assert!(close.confidence() > far.confidence());
+        assert!(Issue::Case("A".to_string(), "a".to_string()).confidence() > close.confidence());
+    }
+
+    #[test]
+    fn indexed_similar_tags_match_every_pair() {
+        let tagset: Set<Tag> = [
+            "rust/async", "Rust/async", "rust/asyncs", "rust/tokio", "python", "pyhton",
+            "to-do", "todo", "ml/papers", "machine-learning/papers", "ml/paper", "2023",
+            "2024", "notes/cats", "notes/cat", "java",
+        ]
+        .iter()
+        .map(|t| tagparse(t))
+        .collect();
+        let f = Filter::default().with_synonyms(&[vec!["ml".to_string(), "machine-learning".to_string()]]);
+
+        let tags: Vec<&Tag> = tagset.iter().collect();
+        let mut every_pair = Vec::new();
+        for (i, ts1) in tags.iter().enumerate() {
+            for ts2 in &tags[i + 1..] {
+                every_pair.extend(f.compare_heirarchical_tags(ts1, ts2));
+            }
+        }
+        assert!(every_pair.len() > 5);
+        assert_eq!(f.similar_tags_in(&tagset), every_pair);
+    }
+
+    #[test]
+    fn aliases_are_the_same_tag() {
+        let aliases: Map<String, String> = [("ml", "machine-learning"), ("JS", "programming/javascript")]
+            .iter()
+            .map(|(a, c)| (a.to_string(), c.to_string()))
+            .collect();
+
+        let f = Filter::new(&["machine-learning"], &[], false).with_aliases(&aliases);
+        assert!(f.matches(&tags(&["ML/papers"])));
+        let f = Filter::new(&["ml"], &[], false).with_aliases(&aliases);
+        assert!(f.matches(&tags(&["machine-learning"])));
+        let f = Filter::new(&["javascript"], &[], false).with_aliases(&aliases);
+        assert!(f.matches(&tags(&["js"])));
+        assert!(!f.matches(&tags(&["json"])));
+
+        let f = Filter::default().with_aliases(&aliases);
+        assert_eq!(f.canonical_tag(&tagparse("js/react")), tagparse("programming/javascript/react"));
+        let counts = f.count_of_tag_sets(&[tags(&["ml"]), tags(&["machine-learning"])]);
+        assert_eq!(counts, vec![(2, "machine-learning".to_string())]);
+        assert_eq!(
+            f.aliases_used(&tags(&["ml", "rust"])),
+            vec![(tagparse("ml"), tagparse("machine-learning"))]
+        );
+        assert!(f.similar_tags_in(&tags(&["ml", "ML", "machine-learning"])).is_empty());
+    }
+
+    #[test]
+    fn explain_matches() {
+        let doc = Document::from_contents(
+            "2024-02-10-gym.md",
+            "value: 150\n\n@rust/async @rust/tokio @Video".to_string(),
+        );
+        let keywords = ["rust", "python", "value>100"];
+        let f = Filter::new(&keywords, &[], false);
+        let explanation = f.explain_document(&doc);
+        assert!(!explanation.matches);
+        assert_eq!(
+            explanation.keywords,
+            vec![("python".to_string(), false), ("rust".to_string(), true)]
+        );
+        let tag = |e: &Explanation, t: &str| -> TagExplanation {
+            e.tags.iter().find(|te| te.tag == tagparse(t)).unwrap().clone()
+        };
+        assert_eq!(tag(&explanation, "rust/async").satisfied, vec!["rust"]);
+        assert!(tag(&explanation, "Video").satisfied.is_empty());
+        assert!(explanation.conditions[0].holds && !explanation.conditions[0].bad);
+        assert_eq!(explanation.date, None);
+
+        let bad = ["video"];
+        let f = Filter::new(&keywords[..1], &bad, false);
+        let explanation = f.explain_document(&doc);
+        assert!(!explanation.matches);
+        assert!(tag(&explanation, "rust/async").violated.is_empty());
+        assert_eq!(tag(&explanation, "Video").violated, vec!["video"]);
+
+        let range = DateRange { since: Date::parse("2024-03-01"), until: None };
+        let f = Filter::new(&keywords[..1], &[], false).with_dates(range, &[DateSource::Filename]);
+        assert_eq!(f.explain_document(&doc).date, Some(false));
+        assert!(!f.explain_document(&doc).matches);
+
+        // The explanation always comes to the same decision
+        let queries: [(&[&str], &[&str], bool); 6] = [
+            (&["rust", "async"], &[], false),
+            (&["rust/tokio", "python"], &[], true),
+            (&["python"], &["video"], true),
+            (&["value<100"], &[], false),
+            (&[], &["value=150"], false),
+            (&[], &[], true),
+        ];
+        let pattern = Regex::new("value").unwrap();
+        for (good, bad, or) in queries.iter() {
+            let f = Filter::new(good, bad, *or).with_pattern(pattern.clone());
+            assert_eq!(f.explain_document(&doc).matches, f.document_matches(&doc));
+        }
+    }
+
+    #[test]
+    fn saved_queries_are_virtual_tags() {
+        let queries: Map<String, Query> = [
+            ("reading", "unread neuralnet NOT archived"),
+            ("spending", "monthly value>20"),
+            ("later", "reading OR @spending"),
+            ("loop", "loop rust"),
+        ]
+        .iter()
+        .map(|(name, query)| (name.to_string(), query.parse().unwrap()))
+        .collect();
+        let unread = tags(&["unread", "neuralnet/cnn"]);
+        let archived = tags(&["unread", "neuralnet", "archived"]);
+
+        let f = Filter::new(&["reading"], &[], false).with_queries(&queries);
+        assert!(f.matches(&unread));
+        assert!(!f.matches(&archived));
+        assert!(!f.matches(&tags(&["reading"])));
+        assert!(f.wants_tag(&tagparse("neuralnet")));
+        let f = Filter::new(&["@reading", "python"], &[], false).with_queries(&queries);
+        assert!(!f.matches(&unread));
+        let f = Filter::new(&["neuralnet"], &["reading"], false).with_queries(&queries);
+        assert!(!f.matches(&unread));
+        assert!(f.matches(&archived));
+
+        let f = Filter::new(&["later"], &[], false).with_queries(&queries);
+        assert!(f.uses_metadata());
+        assert!(f.matches(&unread));
+        assert!(f.matches_with_metadata(&tags(&["monthly"]), &metadata_from_string("value: 25")));
+        assert!(!f.matches_with_metadata(&tags(&["monthly"]), &metadata_from_string("value: 5")));
+
+        // A query using itself looks for its name as a plain keyword
+        let f = Filter::new(&["loop"], &[], false).with_queries(&queries);
+        assert!(f.matches(&tags(&["loop", "rust"])));
+        assert!(!f.matches(&tags(&["rust"])));
+
+        let f = Filter::new(&["cnn"], &["reading"], false).with_queries(&queries);
+        let explanation = f.explain(&unread, &Metadata::new());
+        assert!(!explanation.matches);
+        assert_eq!(
+            explanation.queries,
+            vec![QueryExplanation { name: "reading".to_string(), bad: true, matches: true }]
+        );
+    }
+
+    #[test]
+    fn tags_of_matching_files() {
+        let corpus = Corpus::from_documents(vec![
+            Document::from_contents("a.md", "@python @pandas".to_string()),
+            Document::from_contents("b.md", "@rust @tokio".to_string()),
+        ]);
+        let f = Filter::new(&["python"], &[], false);
+        assert_eq!(f.tags_matching_in(&corpus), [tagparse("pandas"), tagparse("python")].iter().cloned().collect());
+        assert_eq!(Filter::default().tags_matching_in(&corpus).len(), 4);
+    }
+
+    #[test]
+    fn metadata_conditions() {
+        let tags: Set<Tag> = [tagparse("monthly")].iter().cloned().collect();
+        let metadata = metadata_from_string("value: 150\ndate: 2024-02-10\n");
+
+        let f = Filter::new(&["monthly", "value>100"], &[], false);
+        assert!(f.uses_metadata());
+        assert!(f.matches_with_metadata(&tags, &metadata));
+        assert!(!f.matches(&tags));
+        let f = Filter::new(&["monthly", "date<2024-01-01"], &[], false);
+        assert!(!f.matches_with_metadata(&tags, &metadata));
+        let f = Filter::new(&["weekly", "date<2024-01-01", "value=150"], &[], true);
+        assert!(f.matches_with_metadata(&tags, &metadata));
+        let f = Filter::new(&["monthly"], &["value>=150"], false);
+        assert!(!f.matches_with_metadata(&tags, &metadata));
+        let f = Filter::new(&["@monthly"], &[], false);
+        assert!(f.matches(&tags));
+    }
 }
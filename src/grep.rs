@@ -0,0 +1,91 @@
+use std::io::{Error, ErrorKind};
+
+use regex::Regex;
+
+/// Build a pattern to search file contents for
+///
+/// A `literal` pattern matches exactly the text given, otherwise it is a
+/// regular expression (see the `regex` crate for the syntax).
+pub fn build_pattern(pattern: &str, literal: bool) -> Result<Regex, Error> {
+    let pattern = if literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    Regex::new(&pattern).map_err(|e| Error::new(ErrorKind::InvalidInput, e.to_string()))
+}
+
+/// A line of a file shown by `grep_lines`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepLine<'a> {
+    /// Line number, starting from 1
+    pub line: usize,
+    pub text: &'a str,
+    /// Whether this line matched, rather than being shown for context
+    pub is_match: bool,
+}
+
+/// Every line matching `pattern`, along with up to `context` lines either
+/// side
+///
+/// Lines are grouped into runs of consecutive lines, so that overlapping
+/// context is only shown once.
+pub fn grep_lines<'a>(contents: &'a str, pattern: &Regex, context: usize) -> Vec<Vec<GrepLine<'a>>> {
+    let lines: Vec<&str> = contents.lines().collect();
+    let matching: Vec<usize> = (0..lines.len()).filter(|&i| pattern.is_match(lines[i])).collect();
+    let mut groups: Vec<Vec<GrepLine>> = Vec::new();
+    let mut shown_until = 0;
+    for &i in &matching {
+        let start = i.saturating_sub(context).max(shown_until);
+        let end = (i + context + 1).min(lines.len());
+        if start > shown_until || groups.is_empty() {
+            groups.push(Vec::new());
+        }
+        let group = groups.last_mut().expect("a group was just added");
+        for (j, text) in lines.iter().enumerate().take(end).skip(start) {
+            group.push(GrepLine {
+                line: j + 1,
+                text,
+                is_match: pattern.is_match(text),
+            });
+        }
+        shown_until = shown_until.max(end);
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literal_and_regex_patterns() {
+        assert!(build_pattern("a.b", true).unwrap().is_match("see a.b"));
+        assert!(!build_pattern("a.b", true).unwrap().is_match("see axb"));
+        assert!(build_pattern("a.b", false).unwrap().is_match("see axb"));
+        assert!(build_pattern("(unclosed", false).is_err());
+    }
+
+    #[test]
+    fn context_groups() {
+        let contents = "one\nlifetime\nthree\nfour\nfive\nsix\n'a lifetime\neight";
+        let pattern = build_pattern("lifetime", true).unwrap();
+        let lines = |context| -> Vec<Vec<(usize, bool)>> {
+            grep_lines(contents, &pattern, context)
+                .iter()
+                .map(|group| group.iter().map(|l| (l.line, l.is_match)).collect())
+                .collect()
+        };
+        assert_eq!(lines(0), vec![vec![(2, true)], vec![(7, true)]]);
+        assert_eq!(
+            lines(1),
+            vec![
+                vec![(1, false), (2, true), (3, false)],
+                vec![(6, false), (7, true), (8, false)]
+            ]
+        );
+        // Overlapping context is merged into one group
+        assert_eq!(lines(2).len(), 1);
+        assert_eq!(lines(2)[0].len(), 8);
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::io::Error;
+
+use gix::ObjectId;
+
+use super::Tag;
+use crate::metadata::Date;
+use crate::utility::{get_tags_from_string, parse_heirarchical_tag, tag_is_within, EXTENSIONS};
+
+/// A commit, as much as is needed to report on it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    /// Abbreviated commit hash
+    pub id: String,
+    /// Commit time, in seconds since the unix epoch
+    pub time: i64,
+    /// First line of the commit message
+    pub summary: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+}
+
+/// A single file gaining or losing a tag in a commit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagEvent {
+    pub commit: CommitInfo,
+    pub path: String,
+    pub change: Change,
+}
+
+/// How a tag's usage changed over a repository's history
+#[derive(Debug, Default)]
+pub struct TagHistory {
+    /// Every file that gained or lost the tag, oldest first
+    pub events: Vec<TagEvent>,
+    /// The number of files using the tag after every commit, oldest first
+    pub counts: Vec<(CommitInfo, usize)>,
+}
+
+impl TagHistory {
+    /// The first commit in which any file used the tag
+    pub fn first_seen(&self) -> Option<&CommitInfo> {
+        self.counts
+            .iter()
+            .find(|(_, count)| *count > 0)
+            .map(|(commit, _)| commit)
+    }
+
+    /// The last commit in which any file used the tag
+    pub fn last_seen(&self) -> Option<&CommitInfo> {
+        self.counts
+            .iter()
+            .rev()
+            .find(|(_, count)| *count > 0)
+            .map(|(commit, _)| commit)
+    }
+}
+
+pub(crate) fn git_error<E: std::error::Error + Send + Sync + 'static>(e: E) -> Error {
+    Error::other(e)
+}
+
+/// Open the git repository containing `dir`
+pub(crate) fn open_repo(dir: &str) -> Result<gix::Repository, Error> {
+    gix::discover(dir).map_err(git_error)
+}
+
+/// Every file in a tree with an extension we search, mapped to its blob
+pub(crate) fn taggable_blobs(tree: &gix::Tree) -> Result<Map<String, ObjectId>, Error> {
+    let entries = tree.traverse().breadthfirst.files().map_err(git_error)?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.mode.is_blob())
+        .map(|entry| (entry.filepath.to_string(), entry.oid))
+        .filter(|(path, _)| {
+            EXTENSIONS
+                .iter()
+                .any(|ext| path.ends_with(&format!(".{}", ext)))
+        })
+        .collect())
+}
+
+/// Read a blob as text, replacing any invalid UTF-8
+pub(crate) fn blob_contents(repo: &gix::Repository, id: ObjectId) -> Result<String, Error> {
+    let blob = repo.find_blob(id).map_err(git_error)?;
+    Ok(String::from_utf8_lossy(&blob.data).into_owned())
+}
+
+fn commit_info(commit: &gix::Commit) -> Result<CommitInfo, Error> {
+    let time = commit.time().map_err(git_error)?;
+    let message = commit.message().map_err(git_error)?;
+    Ok(CommitInfo {
+        id: commit.id().shorten_or_id().to_string(),
+        time: time.seconds,
+        summary: message.summary().to_string(),
+    })
+}
+
+/// Check if a set of tags includes `tag`, or anything beneath it in the
+/// heirarchy (ignoring case)
+fn has_tag(tags: &Set<Tag>, tag: &[String]) -> bool {
+    tags.iter().any(|t| tag_is_within(t, tag))
+}
+
+/// Trace a tag through the first-parent history leading up to `rev`
+///
+/// `repo_dir` can be anywhere inside a git repository. Each commit's tree is
+/// compared with its parent's, and only files whose contents changed are
+/// re-parsed with `get_tags_from_string`. Nothing is fetched over the network.
+pub fn tag_history(repo_dir: &str, rev: &str, tag: &str) -> Result<TagHistory, Error> {
+    let tag = parse_heirarchical_tag(tag);
+    let repo = open_repo(repo_dir)?;
+    let tip = repo.rev_parse_single(rev).map_err(git_error)?;
+    let mut commits: Vec<ObjectId> = tip
+        .ancestors()
+        .first_parent_only()
+        .all()
+        .map_err(git_error)?
+        .map(|info| info.map(|info| info.id))
+        .collect::<Result<_, _>>()
+        .map_err(git_error)?;
+    commits.reverse();
+
+    let mut history = TagHistory::default();
+    let mut blob_has_tag: Map<ObjectId, bool> = Map::new();
+    let mut previous: Map<String, ObjectId> = Map::new();
+    let mut tagged: Set<String> = Set::new();
+    for id in commits {
+        let commit = repo.find_commit(id).map_err(git_error)?;
+        let info = commit_info(&commit)?;
+        let blobs = taggable_blobs(&commit.tree().map_err(git_error)?)?;
+
+        let removed = previous.keys().filter(|path| !blobs.contains_key(*path));
+        let changed = blobs
+            .iter()
+            .filter(|(path, id)| previous.get(*path) != Some(id))
+            .map(|(path, _)| path);
+        let touched: Vec<String> = removed.chain(changed).cloned().collect();
+        for path in touched {
+            let now_tagged = match blobs.get(&path) {
+                Some(id) => match blob_has_tag.get(id) {
+                    Some(known) => *known,
+                    None => {
+                        let found = has_tag(&get_tags_from_string(&blob_contents(&repo, *id)?), &tag);
+                        blob_has_tag.insert(*id, found);
+                        found
+                    }
+                },
+                None => false,
+            };
+            let change = match (tagged.contains(&path), now_tagged) {
+                (false, true) => Change::Added,
+                (true, false) => Change::Removed,
+                _ => continue,
+            };
+            if now_tagged {
+                tagged.insert(path.clone());
+            } else {
+                tagged.remove(&path);
+            }
+            history.events.push(TagEvent {
+                commit: info.clone(),
+                path,
+                change,
+            });
+        }
+        history.counts.push((info, tagged.len()));
+        previous = blobs;
+    }
+    Ok(history)
+}
+
+/// Format a unix timestamp as a `YYYY-MM-DD` date (in UTC)
+pub fn format_unix_date(seconds: i64) -> String {
+    Date::from_unix(seconds).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(format_unix_date(0), "1970-01-01");
+        assert_eq!(format_unix_date(951_782_400), "2000-02-29");
+        assert_eq!(format_unix_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_unix_date(-86400), "1969-12-31");
+    }
+
+    /// Run git in `dir`, ignoring any user or system config
+    fn git(dir: &std::path::Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com", "-c", "commit.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    #[test]
+    fn history_of_a_repo() {
+        let root = std::env::temp_dir().join(format!("tagsearch-history-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        git(&root, &["init", "-q"]);
+        let commit = |files: &[(&str, Option<&str>)], message: &str| {
+            for (name, contents) in files {
+                match contents {
+                    Some(contents) => std::fs::write(root.join(name), contents).unwrap(),
+                    None => std::fs::remove_file(root.join(name)).unwrap(),
+                }
+            }
+            git(&root, &["add", "-A"]);
+            git(&root, &["commit", "-q", "-m", message]);
+        };
+        commit(&[("a.md", Some("@rust/async")), ("b.md", Some("@python")), ("c.rs", Some("@rust"))], "Add notes");
+        commit(&[("b.md", Some("@python @Rust"))], "Tag b");
+        commit(&[("a.md", Some("no tags"))], "Untag a");
+        commit(&[("b.md", None)], "Delete b");
+
+        let root_str = root.to_string_lossy().into_owned();
+        let history = tag_history(&root_str, "HEAD", "rust").unwrap();
+        let events: Vec<(&str, &str, Change)> = history
+            .events
+            .iter()
+            .map(|e| (e.commit.summary.as_str(), e.path.as_str(), e.change))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("Add notes", "a.md", Change::Added),
+                ("Tag b", "b.md", Change::Added),
+                ("Untag a", "a.md", Change::Removed),
+                ("Delete b", "b.md", Change::Removed),
+            ]
+        );
+        let counts: Vec<usize> = history.counts.iter().map(|(_, count)| *count).collect();
+        assert_eq!(counts, vec![1, 2, 1, 0]);
+        assert_eq!(history.first_seen().unwrap().summary, "Add notes");
+        assert_eq!(history.last_seen().unwrap().summary, "Untag a");
+
+        // Only history up to `rev` is looked at
+        let history = tag_history(&root_str, "HEAD~2", "rust/async").unwrap();
+        assert_eq!(history.counts.len(), 2);
+        assert_eq!(history.events.len(), 1);
+        assert!(tag_history(&root_str, "HEAD", "python").unwrap().last_seen().is_some());
+        assert!(tag_history(&root_str, "HEAD", "java").unwrap().first_seen().is_none());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn heirarchical_has_tag() {
+        let tags: Set<Tag> = [parse_heirarchical_tag("Rust/async")].iter().cloned().collect();
+        assert!(has_tag(&tags, &parse_heirarchical_tag("rust")));
+        assert!(has_tag(&tags, &parse_heirarchical_tag("rust/async")));
+        assert!(!has_tag(&tags, &parse_heirarchical_tag("async")));
+        assert!(!has_tag(&tags, &parse_heirarchical_tag("rust/tokio")));
+    }
+}
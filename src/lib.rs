@@ -1,4 +1,18 @@
-pub mod filter;
-pub mod utility;
-
-pub type Tag = Vec<String>;
+pub mod aggregate;
+pub mod config;
+pub mod corpus;
+pub mod dates;
+pub mod diff;
+pub mod filter;
+pub mod grep;
+pub mod history;
+pub mod lint;
+pub mod materialize;
+pub mod metadata;
+pub mod query;
+pub mod registry;
+mod similar;
+pub mod sort;
+pub mod utility;
+
+pub type Tag = Vec<String>;
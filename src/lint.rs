@@ -0,0 +1,585 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::Tag;
+use crate::filter::Filter;
+use crate::utility::{
+    parse_heirarchical_tag, relative_path, tag_is_within, tag_occurrences, TagOccurrence,
+};
+
+/// How serious a lint finding is
+///
+/// Any finding at `Error` severity should make the linter fail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Don't run the rule at all
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Severity::Off => "off",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Lint settings, from the `[lint]` table of the config (or `[vaults.NAME.lint]`)
+///
+/// ```toml
+/// [lint]
+/// disallowed = ["todo"]
+/// allowlist = ["rust", "python", "unread"]
+/// max_depth = 3
+/// separator = "/"
+///
+/// [lint.required]
+/// "papers" = ["unread"]
+///
+/// [lint.severity]
+/// singleton = "off"
+/// similar = "error"
+/// ```
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    /// Tags (and anything beneath them) which must not be used
+    pub disallowed: Vec<String>,
+    /// If set, the only tags (and anything beneath them) which may be used
+    pub allowlist: Option<Vec<String>>,
+    /// Tags that every file under a directory (relative to the root) must have
+    pub required: Map<String, Vec<String>>,
+    /// Deepest heirarchy allowed, e.g. 2 allows `a/b` but not `a/b/c`
+    pub max_depth: Option<usize>,
+    /// Heirarchy separator to use; by default whichever is used most
+    pub separator: Option<char>,
+    /// Severity of each rule, by rule name, overriding the default
+    #[serde(deserialize_with = "rule_severities")]
+    pub severity: Map<String, Severity>,
+}
+
+/// Names of every built-in rule, as used in `[severity]` tables
+pub const RULE_NAMES: [&str; 8] = [
+    "similar",
+    "singleton",
+    "separator",
+    "disallowed",
+    "unknown",
+    "required",
+    "depth",
+    "deprecated",
+];
+
+/// Read a `[severity]` table, rejecting any rule that doesn't exist (e.g. a
+/// misspelling), as it would otherwise be silently ignored
+fn rule_severities<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map<String, Severity>, D::Error> {
+    let severity = Map::<String, Severity>::deserialize(deserializer)?;
+    match severity.keys().find(|name| !RULE_NAMES.contains(&name.as_str())) {
+        Some(name) => Err(serde::de::Error::custom(format!(
+            "Unknown lint rule `{}`, expected one of {}",
+            name,
+            RULE_NAMES.join(", ")
+        ))),
+        None => Ok(severity),
+    }
+}
+
+/// A file's tags, as needed by the lint rules
+pub struct LintFile {
+    /// Path, as given to the linter
+    pub path: String,
+    /// Path relative to the root of the vault
+    pub relative: String,
+    /// Every tag occurrence, with the tag's text as written (without the `@`)
+    pub tags: Vec<(TagOccurrence, String)>,
+}
+
+impl LintFile {
+    /// Find a file's tags in its contents, recording its path relative to `root`
+    pub fn from_contents(path: &str, root: &str, contents: &str) -> LintFile {
+        let relative = relative_path(path, root);
+        let tags = tag_occurrences(contents)
+            .map(|o| {
+                let raw = contents[o.byte_range.start + 1..o.byte_range.end].to_string();
+                (o, raw)
+            })
+            .collect();
+        LintFile {
+            path: path.to_string(),
+            relative,
+            tags,
+        }
+    }
+}
+
+/// Something a rule found wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Where the problem is, if it can be pinned to a single tag or file
+    pub path: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl Finding {
+    fn at(file: &LintFile, occurrence: &TagOccurrence, message: String) -> Finding {
+        Finding {
+            path: Some(file.path.clone()),
+            line: Some(occurrence.line),
+            column: Some(occurrence.column),
+            message,
+        }
+    }
+}
+
+/// A finding, labelled with the rule that found it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub path: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.path, self.line, self.column) {
+            (Some(path), Some(line), Some(column)) => write!(f, "{}:{}:{}: ", path, line, column)?,
+            (Some(path), _, _) => write!(f, "{}: ", path)?,
+            _ => {}
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.rule, self.message)
+    }
+}
+
+/// A check run over every file being linted
+pub trait Rule {
+    /// Name used for the rule in config and in reports
+    fn name(&self) -> &'static str;
+    fn default_severity(&self) -> Severity;
+    fn check(&self, files: &[LintFile]) -> Vec<Finding>;
+}
+
+/// Runs a set of rules, each at some severity
+pub struct Linter {
+    rules: Vec<(Box<dyn Rule>, Severity)>,
+}
+
+impl Linter {
+    /// Create a `Linter` with the built-in rules enabled by `config`, where
+    /// `similar` is how to find similar tags
+    pub fn new(config: &LintConfig, similar: SimilarTags) -> Linter {
+        let mut linter = Linter { rules: Vec::new() };
+        linter.add_rule(Box::new(similar), config);
+        linter.add_rule(Box::new(Singletons), config);
+        linter.add_rule(Box::new(MixedSeparators(config.separator)), config);
+        if !config.disallowed.is_empty() {
+            linter.add_rule(Box::new(Disallowed(parse_all(&config.disallowed))), config);
+        }
+        if let Some(allowlist) = &config.allowlist {
+            linter.add_rule(Box::new(Unknown(parse_all(allowlist))), config);
+        }
+        if !config.required.is_empty() {
+            let required = config
+                .required
+                .iter()
+                .map(|(dir, tags)| (dir.to_string(), parse_all(tags)))
+                .collect();
+            linter.add_rule(Box::new(Required(required)), config);
+        }
+        if let Some(depth) = config.max_depth {
+            linter.add_rule(Box::new(MaxDepth(depth)), config);
+        }
+        linter
+    }
+
+    /// Add another rule, at the severity given in `config` or its default
+    pub fn add_rule(&mut self, rule: Box<dyn Rule>, config: &LintConfig) {
+        let severity = config
+            .severity
+            .get(rule.name())
+            .copied()
+            .unwrap_or_else(|| rule.default_severity());
+        if severity != Severity::Off {
+            self.rules.push((rule, severity));
+        }
+    }
+
+    /// Run every rule, returning what they found
+    pub fn lint(&self, files: &[LintFile]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (rule, severity) in &self.rules {
+            for finding in rule.check(files) {
+                diagnostics.push(Diagnostic {
+                    rule: rule.name(),
+                    severity: *severity,
+                    path: finding.path,
+                    line: finding.line,
+                    column: finding.column,
+                    message: finding.message,
+                });
+            }
+        }
+        diagnostics
+    }
+}
+
+fn parse_all(tags: &[String]) -> Vec<Tag> {
+    tags.iter().map(|t| parse_heirarchical_tag(t)).collect()
+}
+
+/// Every tag occurrence across all files
+fn all_tags(files: &[LintFile]) -> impl Iterator<Item = (&LintFile, &TagOccurrence, &str)> {
+    files
+        .iter()
+        .flat_map(|file| file.tags.iter().map(move |(o, raw)| (file, o, raw.as_str())))
+}
+
+/// Tags that look like they are meant to be the same, as with `similar-tags`
+///
+/// As there, `synonyms` are groups of words that mean the same thing, and
+/// tags that are aliases of each other aren't reported.
+#[derive(Debug, Default)]
+pub struct SimilarTags {
+    pub synonyms: Vec<Vec<String>>,
+    pub aliases: Map<String, String>,
+}
+
+impl Rule for SimilarTags {
+    fn name(&self) -> &'static str {
+        "similar"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        let f = Filter::default()
+            .with_synonyms(&self.synonyms)
+            .with_aliases(&self.aliases);
+        let tagset: Set<Tag> = all_tags(files).map(|(_, o, _)| o.tag.clone()).collect();
+        f.similar_tags_in(&tagset)
+            .into_iter()
+            .map(|issue| {
+                let second = issue.tags().1.to_string();
+                // Point at the first use of the second spelling (or any alias of it)
+                match all_tags(files).find(|(_, o, _)| f.canonical_tag(&o.tag).join("/") == second) {
+                    Some((file, o, _)) => Finding::at(file, o, issue.to_string()),
+                    None => Finding {
+                        path: None,
+                        line: None,
+                        column: None,
+                        message: issue.to_string(),
+                    },
+                }
+            })
+            .collect()
+    }
+}
+
+/// Tags used in only a single file, which are often typos
+pub struct Singletons;
+
+impl Rule for Singletons {
+    fn name(&self) -> &'static str {
+        "singleton"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Info
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        let mut used_in: Map<String, Set<&str>> = Map::new();
+        for (file, o, _) in all_tags(files) {
+            used_in
+                .entry(o.tag.join("/"))
+                .or_default()
+                .insert(file.path.as_str());
+        }
+        let mut reported = Set::new();
+        all_tags(files)
+            .filter(|(_, o, _)| {
+                let tag = o.tag.join("/");
+                used_in[&tag].len() == 1 && reported.insert(tag)
+            })
+            .map(|(file, o, raw)| Finding::at(file, o, format!("`{}` is only used in one file", raw)))
+            .collect()
+    }
+}
+
+/// Tags using a different heirarchy separator to the rest of the files
+pub struct MixedSeparators(pub Option<char>);
+
+impl Rule for MixedSeparators {
+    fn name(&self) -> &'static str {
+        "separator"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        let preferred = self.0.unwrap_or_else(|| {
+            let count = |sep| all_tags(files).filter(|(_, _, raw)| raw.contains(sep)).count();
+            if count(':') > count('/') {
+                ':'
+            } else {
+                '/'
+            }
+        });
+        let other = if preferred == ':' { '/' } else { ':' };
+        all_tags(files)
+            .filter(|(_, _, raw)| raw.contains(other))
+            .map(|(file, o, raw)| {
+                Finding::at(file, o, format!("`{}` should use `{}` as a separator", raw, preferred))
+            })
+            .collect()
+    }
+}
+
+/// Tags that must not be used
+pub struct Disallowed(pub Vec<Tag>);
+
+impl Rule for Disallowed {
+    fn name(&self) -> &'static str {
+        "disallowed"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        all_tags(files)
+            .filter(|(_, o, _)| self.0.iter().any(|bad| tag_is_within(&o.tag, bad)))
+            .map(|(file, o, raw)| Finding::at(file, o, format!("`{}` is not allowed", raw)))
+            .collect()
+    }
+}
+
+/// Tags that aren't in the allowlist
+pub struct Unknown(pub Vec<Tag>);
+
+impl Rule for Unknown {
+    fn name(&self) -> &'static str {
+        "unknown"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        all_tags(files)
+            .filter(|(_, o, _)| !self.0.iter().any(|known| tag_is_within(&o.tag, known)))
+            .map(|(file, o, raw)| Finding::at(file, o, format!("`{}` is not in the allowlist", raw)))
+            .collect()
+    }
+}
+
+/// Tags that every file under a directory must have
+pub struct Required(pub Vec<(String, Vec<Tag>)>);
+
+impl Rule for Required {
+    fn name(&self) -> &'static str {
+        "required"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Error
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (dir, required) in &self.0 {
+            for file in files.iter().filter(|f| Path::new(&f.relative).starts_with(dir)) {
+                for tag in required {
+                    if !file.tags.iter().any(|(o, _)| tag_is_within(&o.tag, tag)) {
+                        findings.push(Finding {
+                            path: Some(file.path.clone()),
+                            line: None,
+                            column: None,
+                            message: format!("files in `{}` must be tagged `{}`", dir, tag.join("/")),
+                        });
+                    }
+                }
+            }
+        }
+        findings
+    }
+}
+
+/// Tags marked in the registry as deprecated, with their replacements
+pub struct Deprecated(pub Vec<(Tag, String)>);
+
+impl Rule for Deprecated {
+    fn name(&self) -> &'static str {
+        "deprecated"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        for (file, o, raw) in all_tags(files) {
+            if let Some((_, replacement)) = self.0.iter().find(|(old, _)| tag_is_within(&o.tag, old)) {
+                findings.push(Finding::at(
+                    file,
+                    o,
+                    format!("`{}` is deprecated, use `{}` instead", raw, replacement),
+                ));
+            }
+        }
+        findings
+    }
+}
+
+/// Tags nested more deeply than allowed
+pub struct MaxDepth(pub usize);
+
+impl Rule for MaxDepth {
+    fn name(&self) -> &'static str {
+        "depth"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, files: &[LintFile]) -> Vec<Finding> {
+        all_tags(files)
+            .filter(|(_, o, _)| o.tag.len() > self.0)
+            .map(|(file, o, raw)| {
+                Finding::at(
+                    file,
+                    o,
+                    format!("`{}` is nested deeper than {} levels", raw, self.0),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(contents: &[(&str, &str)]) -> Vec<LintFile> {
+        contents
+            .iter()
+            .map(|(path, text)| LintFile::from_contents(path, "notes", text))
+            .collect()
+    }
+
+    fn rules_found(config: &LintConfig, contents: &[(&str, &str)]) -> Vec<(&'static str, String)> {
+        Linter::new(config, SimilarTags::default())
+            .lint(&files(contents))
+            .into_iter()
+            .map(|d| (d.rule, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn default_rules() {
+        let found = rules_found(
+            &LintConfig::default(),
+            &[("notes/a.md", "@Rust @a/b @c"), ("notes/b.md", "@rust @a/b @x:y")],
+        );
+        assert!(found.contains(&("similar", "Case - Rust & rust".to_string())));
+        assert!(found.contains(&("singleton", "`c` is only used in one file".to_string())));
+        assert!(found.contains(&("separator", "`x:y` should use `/` as a separator".to_string())));
+        assert!(!found.iter().any(|(_, msg)| msg.contains("a/b")));
+    }
+
+    #[test]
+    fn similar_tags_with_synonyms_and_aliases() {
+        let similar = SimilarTags {
+            synonyms: vec![vec!["ai".to_string(), "artificial-intelligence".to_string()]],
+            aliases: [("ml".to_string(), "machine-learning".to_string())].iter().cloned().collect(),
+        };
+        let diagnostics = Linter::new(&LintConfig::default(), similar).lint(&files(&[
+            ("notes/a.md", "@ai @ml @machine-learning"),
+            ("notes/b.md", "@artificial-intelligence @ml/papers @machine-learning/papers"),
+        ]));
+        let found: Vec<String> = diagnostics
+            .iter()
+            .filter(|d| d.rule == "similar")
+            .map(|d| format!("{} {:?}", d.message, d.path))
+            .collect();
+        assert_eq!(found, vec!["Synonym - ai & artificial-intelligence Some(\"notes/b.md\")"]);
+    }
+
+    #[test]
+    fn configured_rules() {
+        let config: LintConfig = toml::from_str(
+            r#"
+            disallowed = ["todo"]
+            allowlist = ["rust", "todo", "unread"]
+            max_depth = 2
+            [required]
+            "papers" = ["unread"]
+            [severity]
+            singleton = "off"
+            similar = "off"
+            "#,
+        )
+        .unwrap();
+        let diagnostics = Linter::new(&config, SimilarTags::default()).lint(&files(&[
+            ("notes/papers/a.md", "@rust/a/b @todo/later"),
+            ("notes/b.md", "@python"),
+        ]));
+        let found: Vec<(&str, Severity)> = diagnostics.iter().map(|d| (d.rule, d.severity)).collect();
+        assert_eq!(
+            found,
+            vec![
+                ("disallowed", Severity::Error),
+                ("unknown", Severity::Error),
+                ("required", Severity::Error),
+                ("depth", Severity::Warning),
+            ]
+        );
+        assert_eq!(diagnostics[2].path, Some("notes/papers/a.md".to_string()));
+
+        // However the root is written
+        for root in ["./notes", "notes/"].iter() {
+            let file = LintFile::from_contents("./notes/papers/b.md", root, "@rust");
+            assert_eq!(file.relative, "papers/b.md");
+            assert!(Linter::new(&config, SimilarTags::default()).lint(&[file]).iter().any(|d| d.rule == "required"));
+        }
+    }
+
+    #[test]
+    fn deprecated_tags() {
+        let config = LintConfig::default();
+        let mut linter = Linter::new(&config, SimilarTags::default());
+        linter.add_rule(
+            Box::new(Deprecated(vec![(parse_heirarchical_tag("ml"), "machine-learning".to_string())])),
+            &config,
+        );
+        let found: Vec<String> = linter
+            .lint(&files(&[("notes/a.md", "@ML/papers @html")]))
+            .into_iter()
+            .filter(|d| d.rule == "deprecated")
+            .map(|d| d.message)
+            .collect();
+        assert_eq!(found, vec!["`ML/papers` is deprecated, use `machine-learning` instead"]);
+    }
+}
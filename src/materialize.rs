@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::io::{Error, ErrorKind};
+use std::path::{Component, Path, PathBuf};
+
+use super::Tag;
+
+/// File marking a directory as a tag view, so stale links are only ever
+/// removed from directories that `materialize` made
+pub const MARKER: &str = ".tagsearch-view";
+
+/// Whether a directory is a tag view, which only links to files found
+/// elsewhere, so shouldn't be searched itself
+pub fn is_view(dir: &Path) -> bool {
+    dir.join(MARKER).exists()
+}
+
+/// Where each file should be linked from in a tag view
+///
+/// Each tag gets a directory, nested by its heirarchy (so `@rust/async` is
+/// `rust/async/`), with a link to each file carrying that tag, named after
+/// the file. Files in the same directory with the same name are named after
+/// their whole path instead, e.g. `notes__a.md` and `work__a.md`. Tags with a
+/// component that can't be a directory name (e.g. `..`) are skipped.
+///
+/// Returns each link's path within the view, and the file it links to.
+pub fn view_links(files: &[(String, Set<Tag>)]) -> Map<PathBuf, String> {
+    let mut by_dir: Map<PathBuf, Vec<&str>> = Map::new();
+    for (path, tags) in files {
+        for tag in tags {
+            if let Some(dir) = tag_dir(tag) {
+                by_dir.entry(dir).or_default().push(path);
+            }
+        }
+    }
+    let mut links = Map::new();
+    for (dir, paths) in by_dir {
+        let mut names: Map<String, usize> = Map::new();
+        for path in &paths {
+            *names.entry(file_name(path)).or_insert(0) += 1;
+        }
+        for path in paths {
+            let name = match names[&file_name(path)] {
+                1 => file_name(path),
+                _ => flat_name(path),
+            };
+            links.insert(dir.join(name), path.to_string());
+        }
+    }
+    links
+}
+
+fn tag_dir(tag: &Tag) -> Option<PathBuf> {
+    let usable = |c: &String| !c.is_empty() && c != "." && c != ".." && !c.contains(['/', '\\']);
+    if tag.is_empty() || !tag.iter().all(usable) {
+        return None;
+    }
+    Some(tag.iter().collect())
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map_or_else(|| flat_name(path), |name| name.to_string_lossy().into_owned())
+}
+
+/// A path as a single file name, e.g. `notes__a.md` for `./notes/a.md`
+fn flat_name(path: &str) -> String {
+    let parts: Vec<String> = Path::new(path)
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    parts.join("__")
+}
+
+/// The changes needed to bring a tag view up to date
+#[derive(Debug, Default, PartialEq)]
+pub struct Changes {
+    /// Links to make (replacing any link already there), relative to the
+    /// view, with the absolute path of the file each links to
+    pub create: Vec<(PathBuf, PathBuf)>,
+    /// Links to remove, relative to the view
+    pub remove: Vec<PathBuf>,
+    /// How many links already point at the right file
+    pub unchanged: usize,
+}
+
+/// Compare a tag view in `outdir` against the links it should contain (see
+/// `view_links`)
+///
+/// `outdir` must not exist yet, be empty, or be a tag view already (i.e.
+/// contain `MARKER`). Only links are ever changed, so it's an error for
+/// anything else to be where a link should go.
+pub fn plan_changes(outdir: &Path, links: &Map<PathBuf, String>) -> Result<Changes, Error> {
+    let existing = if outdir.exists() {
+        if !is_view(outdir) && std::fs::read_dir(outdir)?.next().is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("`{}` isn't empty, and isn't a tag view", outdir.display()),
+            ));
+        }
+        existing_links(outdir)?
+    } else {
+        Map::new()
+    };
+
+    let mut changes = Changes::default();
+    for (link, file) in links {
+        let target = std::fs::canonicalize(file).map_err(|e| {
+            Error::new(e.kind(), format!("Couldn't find file `{}`: {}", file, e))
+        })?;
+        match existing.get(link) {
+            Some(current) if *current == target => changes.unchanged += 1,
+            Some(_) => changes.create.push((link.to_path_buf(), target)),
+            None => {
+                if outdir.join(link).symlink_metadata().is_ok() {
+                    return Err(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("`{}` isn't a link, so won't be replaced", outdir.join(link).display()),
+                    ));
+                }
+                changes.create.push((link.to_path_buf(), target));
+            }
+        }
+    }
+    changes.remove = existing
+        .into_keys()
+        .filter(|link| !links.contains_key(link))
+        .collect();
+    Ok(changes)
+}
+
+/// Every link under `root`, relative to it, with the path it links to
+fn existing_links(root: &Path) -> Result<Map<PathBuf, PathBuf>, Error> {
+    let mut links = Map::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let kind = entry.file_type()?;
+            if kind.is_symlink() {
+                let link = entry.path().strip_prefix(root).unwrap().to_path_buf();
+                links.insert(link, std::fs::read_link(entry.path())?);
+            } else if kind.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    Ok(links)
+}
+
+/// Make the changes to the tag view in `outdir`, creating it if needed
+///
+/// Directories left empty by removing links are removed too.
+pub fn apply_changes(outdir: &Path, changes: &Changes) -> Result<(), Error> {
+    std::fs::create_dir_all(outdir)?;
+    std::fs::write(outdir.join(MARKER), "")?;
+    for link in &changes.remove {
+        std::fs::remove_file(outdir.join(link))?;
+        for dir in link.ancestors().skip(1).take_while(|d| !d.as_os_str().is_empty()) {
+            if std::fs::remove_dir(outdir.join(dir)).is_err() {
+                break;
+            }
+        }
+    }
+    for (link, target) in &changes.create {
+        let path = outdir.join(link);
+        if path.symlink_metadata().is_ok() {
+            std::fs::remove_file(&path)?;
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        symlink(target, &path)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> Result<(), Error> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> Result<(), Error> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::parse_heirarchical_tag as tagparse;
+
+    fn tagged(path: &str, tags: &[&str]) -> (String, Set<Tag>) {
+        (path.to_string(), tags.iter().map(|t| tagparse(t)).collect())
+    }
+
+    #[test]
+    fn links_for_each_tag() {
+        let links = view_links(&[
+            tagged("notes/a.md", &["rust/async", "todo"]),
+            tagged("./work/a.md", &["todo", ".."]),
+            tagged("b.txt", &["rust"]),
+        ]);
+        let expected: Map<PathBuf, String> = [
+            ("rust/async/a.md", "notes/a.md"),
+            ("rust/b.txt", "b.txt"),
+            ("todo/notes__a.md", "notes/a.md"),
+            ("todo/work__a.md", "./work/a.md"),
+        ]
+        .iter()
+        .map(|(link, file)| (PathBuf::from(link), file.to_string()))
+        .collect();
+        assert_eq!(links, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_a_view() {
+        let root = std::env::temp_dir().join(format!("tagsearch-view-{}", std::process::id()));
+        let notes = root.join("notes");
+        let view = root.join("view");
+        std::fs::create_dir_all(&notes).unwrap();
+        let note = |name: &str| notes.join(name).to_string_lossy().into_owned();
+        std::fs::write(note("a.md"), "").unwrap();
+        std::fs::write(note("b.md"), "").unwrap();
+
+        let before = view_links(&[tagged(&note("a.md"), &["rust/async"]), tagged(&note("b.md"), &["rust"])]);
+        let changes = plan_changes(&view, &before).unwrap();
+        assert_eq!(changes.create.len(), 2);
+        apply_changes(&view, &changes).unwrap();
+        assert_eq!(std::fs::read_link(view.join("rust/async/a.md")).unwrap(), notes.join("a.md").canonicalize().unwrap());
+
+        // Only what changed is touched, and emptied directories go too
+        let after = view_links(&[tagged(&note("a.md"), &["python"]), tagged(&note("b.md"), &["rust"])]);
+        let changes = plan_changes(&view, &after).unwrap();
+        assert_eq!(changes.unchanged, 1);
+        assert_eq!(changes.remove, vec![PathBuf::from("rust/async/a.md")]);
+        apply_changes(&view, &changes).unwrap();
+        assert!(!view.join("rust/async").exists());
+        assert!(view.join("rust/b.md").exists());
+        assert!(view.join("python/a.md").exists());
+        assert_eq!(plan_changes(&view, &after).unwrap(), Changes { unchanged: 2, ..Changes::default() });
+
+        // Anything that isn't a link is left alone
+        std::fs::write(view.join("python/mine.md"), "").unwrap();
+        std::fs::remove_file(view.join("rust/b.md")).unwrap();
+        std::fs::write(view.join("rust/b.md"), "").unwrap();
+        assert!(plan_changes(&view, &after).is_err());
+        assert!(plan_changes(&notes, &after).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,365 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap as Map;
+
+/// Typed `key: value` fields of a file, keyed by lowercase name
+pub type Metadata = Map<String, Value>;
+
+/// A calendar date, as written `YYYY-MM-DD`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// Parse a `YYYY-MM-DD` date, optionally followed by a time (after a `T`
+    /// or space), which is ignored
+    pub fn parse(s: &str) -> Option<Date> {
+        let date = s.get(..10)?;
+        match s[10..].chars().next() {
+            None | Some('T') | Some(' ') => {}
+            _ => return None,
+        }
+        let mut parts = date.split('-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+            return None;
+        }
+        if ![year, month, day].iter().all(|p| p.chars().all(|c| c.is_ascii_digit())) {
+            return None;
+        }
+        let date = Date {
+            year: year.parse().ok()?,
+            month: month.parse().ok()?,
+            day: day.parse().ok()?,
+        };
+        if (1..=12).contains(&date.month) && (1..=date.days_in_month()).contains(&date.day) {
+            Some(date)
+        } else {
+            None
+        }
+    }
+
+    /// Number of days in this date's month, e.g. 29 for February in a leap year
+    fn days_in_month(&self) -> u32 {
+        let leap = self.year % 4 == 0 && (self.year % 100 != 0 || self.year % 400 == 0);
+        match self.month {
+            2 if leap => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+
+    /// The date (in UTC) of a unix timestamp
+    pub fn from_unix(seconds: i64) -> Date {
+        // Days since epoch to civil date, from Howard Hinnant's `civil_from_days`
+        let z = seconds.div_euclid(86400) + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Date {
+            year: year as i32,
+            month: month as u32,
+            day: day as u32,
+        }
+    }
+
+    /// Find the first date in some text, written either `YYYY-MM-DD` or
+    /// `YYYYMMDD` (and not part of a longer number)
+    pub fn find_in(text: &str) -> Option<Date> {
+        let bytes = text.as_bytes();
+        let digit_at = |i: usize| bytes.get(i).is_some_and(|b| b.is_ascii_digit());
+        (0..bytes.len())
+            .filter(|&i| digit_at(i) && (i == 0 || !digit_at(i - 1)))
+            .find_map(|i| {
+                let (dashed, compact) = (text.get(i..i + 10), text.get(i..i + 8));
+                if let Some(date) = dashed.filter(|_| !digit_at(i + 10)).and_then(Date::parse) {
+                    return Some(date);
+                }
+                // All ASCII digits, so the slices below are on char boundaries
+                let compact = compact.filter(|c| !digit_at(i + 8) && c.bytes().all(|b| b.is_ascii_digit()))?;
+                Date::parse(&format!("{}-{}-{}", &compact[..4], &compact[4..6], &compact[6..]))
+            })
+    }
+
+    /// The month this date is in, as `YYYY-MM`
+    pub fn month_string(&self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl std::str::FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Date, String> {
+        Date::parse(s).ok_or_else(|| format!("Invalid date `{}`, expected YYYY-MM-DD", s))
+    }
+}
+
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A metadata value, typed by what it looks like
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Date(Date),
+    Text(String),
+}
+
+impl Value {
+    pub fn parse(s: &str) -> Value {
+        let s = s.trim();
+        let numeric = !s.is_empty()
+            && s.chars().any(|c| c.is_ascii_digit())
+            && s.chars().all(|c| c.is_ascii_digit() || "+-.".contains(c));
+        if let (true, Ok(n)) = (numeric, s.parse()) {
+            return Value::Number(n);
+        }
+        match Date::parse(s) {
+            Some(date) => Value::Date(date),
+            None => Value::Text(s.to_string()),
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_date(&self) -> Option<Date> {
+        match self {
+            Value::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// Compare two values of the same type, or as text if they differ
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Date(a), Value::Date(b)) => Some(a.cmp(b)),
+            (a, b) => Some(a.to_string().to_lowercase().cmp(&b.to_string().to_lowercase())),
+        }
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Date(d) => write!(f, "{}", d),
+            Value::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+fn is_key(key: &str) -> bool {
+    key.starts_with(|c: char| c.is_alphabetic())
+        && key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Get the metadata from some text
+///
+/// Metadata is either a `key: value` line in the header of the file (the
+/// lines before the first line that isn't one, optionally inside `---`
+/// fences), or a `@key=value` tag anywhere in the file. Later fields replace
+/// earlier ones with the same key.
+///
+/// ```text
+/// title: Gym membership
+/// value: 42
+/// date: 2024-03-01
+///
+/// @monthly @health @store=gym
+/// ```
+pub fn metadata_from_string(contents: &str) -> Metadata {
+    let mut metadata = Metadata::new();
+    let mut lines = contents.trim_start_matches('\u{feff}').lines().peekable();
+    if lines.peek().map(|l| l.trim()) == Some("---") {
+        lines.next();
+    }
+    for line in lines {
+        let field = line
+            .split_once(':')
+            .filter(|(key, _)| is_key(key))
+            .filter(|(_, value)| value.starts_with(' ') || value.is_empty());
+        match field {
+            Some((key, value)) => {
+                metadata.insert(key.to_lowercase(), Value::parse(value));
+            }
+            None => break,
+        }
+    }
+    for word in contents.split_whitespace() {
+        let field = word
+            .strip_prefix('@')
+            .and_then(|w| w.split_once('='))
+            .filter(|(key, value)| is_key(key) && !value.is_empty());
+        if let Some((key, value)) = field {
+            metadata.insert(key.to_lowercase(), Value::parse(value));
+        }
+    }
+    metadata
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A comparison against a metadata field, e.g. `value>100`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub key: String,
+    pub op: Op,
+    pub value: Value,
+}
+
+impl Condition {
+    /// Parse `KEY OP VALUE`, where `OP` is one of `=`, `!=`, `<`, `<=`, `>`,
+    /// or `>=`. Anything else (e.g. a plain tag keyword) gives `None`.
+    pub fn parse(s: &str) -> Option<Condition> {
+        let start = s.find(|c| "=!<>".contains(c))?;
+        let (key, rest) = s.split_at(start);
+        let (op, value) = [
+            ("<=", Op::Le),
+            (">=", Op::Ge),
+            ("!=", Op::Ne),
+            ("<", Op::Lt),
+            (">", Op::Gt),
+            ("=", Op::Eq),
+        ]
+        .iter()
+        .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (*op, value)))?;
+        if !is_key(key) || value.is_empty() {
+            return None;
+        }
+        Some(Condition {
+            key: key.to_lowercase(),
+            op,
+            value: Value::parse(value),
+        })
+    }
+
+    /// Check the condition against a file's metadata
+    ///
+    /// A file without the field never matches, whatever the comparison.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let ordering = match metadata.get(&self.key).and_then(|v| v.compare(&self.value)) {
+            Some(ordering) => ordering,
+            None => return false,
+        };
+        match self.op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let op = match self.op {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        };
+        write!(f, "{}{}{}", self.key, op, self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(Value::parse(" 42"), Value::Number(42.0));
+        assert_eq!(Value::parse("-3.5"), Value::Number(-3.5));
+        assert_eq!(
+            Value::parse("2024-03-01"),
+            Value::Date(Date { year: 2024, month: 3, day: 1 })
+        );
+        assert_eq!(Value::parse("2024-03-01T10:00").as_date().unwrap().to_string(), "2024-03-01");
+        assert_eq!(Value::parse("2024-13-01"), Value::Text("2024-13-01".to_string()));
+        assert_eq!(Value::parse("2024-02-31"), Value::Text("2024-02-31".to_string()));
+        assert_eq!(Value::parse("2024-04-31"), Value::Text("2024-04-31".to_string()));
+        assert!(Date::parse("2024-02-29").is_some());
+        assert!(Date::parse("2000-02-29").is_some());
+        assert!(Date::parse("2023-02-29").is_none());
+        assert!(Date::parse("1900-02-29").is_none());
+        assert_eq!(Value::parse("nan"), Value::Text("nan".to_string()));
+        assert_eq!(Value::parse("v1.2").to_string(), "v1.2");
+    }
+
+    #[test]
+    fn find_dates() {
+        let date = |s: &str| Date::find_in(s).map(|d| d.to_string());
+        assert_eq!(date("20191210--shoulderpress.txt").as_deref(), Some("2019-12-10"));
+        assert_eq!(date("Meeting on 2024-03-01, then").as_deref(), Some("2024-03-01"));
+        assert_eq!(date("id 1234567890 then 20240229").as_deref(), Some("2024-02-29"));
+        assert_eq!(date("no dates, 2024-3-1 or 99999999"), None);
+        assert_eq!(date("20230229 isn't, 20230228 is").as_deref(), Some("2023-02-28"));
+        assert_eq!(date("123é456 and 12é4-56-7 then 2024é0229"), None);
+        assert_eq!(date("123é456 20240229").as_deref(), Some("2024-02-29"));
+        assert_eq!(Date::from_unix(1_709_251_199).to_string(), "2024-02-29");
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let metadata = metadata_from_string(
+            "title: Gym membership\nValue: 42\ndate: 2024-03-01\n\nnotes: not a header\n@monthly @store=gym @value=50",
+        );
+        assert_eq!(metadata["title"], Value::Text("Gym membership".to_string()));
+        assert_eq!(metadata["value"], Value::Number(50.0));
+        assert_eq!(metadata["date"].to_string(), "2024-03-01");
+        assert_eq!(metadata["store"].to_string(), "gym");
+        assert!(!metadata.contains_key("notes"));
+        assert!(!metadata.contains_key("monthly"));
+
+        let fenced = metadata_from_string("---\nvalue: 1\n---\nhttps://example.com\n");
+        assert_eq!(fenced.len(), 1);
+    }
+
+    #[test]
+    fn conditions() {
+        let metadata = metadata_from_string("value: 150\ndate: 2024-02-10\nstore: Gym\n");
+        let check = |s: &str| Condition::parse(s).unwrap().matches(&metadata);
+        assert!(check("value>100"));
+        assert!(!check("value<=100"));
+        assert!(check("value!=100"));
+        assert!(check("date>=2024-01-01"));
+        assert!(!check("date<2024-02-10"));
+        assert!(check("store=gym"));
+        assert!(!check("missing=1"));
+        assert!(!check("missing!=1"));
+        assert!(Condition::parse("rust").is_none());
+        assert!(Condition::parse("=1").is_none());
+        assert!(Condition::parse("value>").is_none());
+        assert_eq!(Condition::parse("Value>=1.5").unwrap().to_string(), "value>=1.5");
+    }
+}
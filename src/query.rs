@@ -0,0 +1,70 @@
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+/// A query written as one string, e.g. `unread AND neuralnet AND NOT archived`
+///
+/// Words are keywords, as given to `files`. `AND` between them is optional,
+/// `OR` between them matches ANY rather than ALL of them (so can't be mixed
+/// with `AND`), and `NOT` before a word excludes files matching it. Only
+/// upper-case operators are special, so `not` and `or` can still be tags.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub good: Vec<String>,
+    pub bad: Vec<String>,
+    pub or: bool,
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Query, Error> {
+        let invalid = |reason: &str| {
+            Error::new(ErrorKind::InvalidInput, format!("Invalid query `{}`: {}", s, reason))
+        };
+        let mut query = Query::default();
+        let (mut and, mut or) = (false, false);
+        let mut words = s.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "AND" => and = true,
+                "OR" => or = true,
+                "NOT" => match words.next() {
+                    Some("AND") | Some("OR") | Some("NOT") | None => {
+                        return Err(invalid("NOT must be followed by a keyword"))
+                    }
+                    Some(word) => query.bad.push(word.to_string()),
+                },
+                word => query.good.push(word.to_string()),
+            }
+        }
+        if and && or {
+            return Err(invalid("can't use both AND and OR"));
+        }
+        query.or = or;
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_queries() {
+        let query: Query = "unread AND neuralnet AND NOT archived".parse().unwrap();
+        assert_eq!(query.good, vec!["unread", "neuralnet"]);
+        assert_eq!(query.bad, vec!["archived"]);
+        assert!(!query.or);
+        assert_eq!(query, "unread neuralnet NOT archived".parse().unwrap());
+
+        let query: Query = "spanish OR espanol value>10".parse().unwrap();
+        assert_eq!(query.good, vec!["spanish", "espanol", "value>10"]);
+        assert!(query.or);
+        let query: Query = "not or".parse().unwrap();
+        assert_eq!(query.good, vec!["not", "or"]);
+
+        assert!("a AND b OR c".parse::<Query>().is_err());
+        assert!("a AND NOT".parse::<Query>().is_err());
+        assert!("a NOT OR b".parse::<Query>().is_err());
+    }
+}
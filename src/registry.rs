@@ -0,0 +1,167 @@
+use std::collections::BTreeMap as Map;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::Tag;
+use crate::utility::parse_heirarchical_tag;
+
+/// Name of the registry file looked for in the root of a vault
+pub const REGISTRY_FILENAME: &str = "tags.toml";
+
+/// What a tag means, from the registry
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TagInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Who to ask about the tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Colour to show the tag in, e.g. `red` or `cyan`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colour: Option<String>,
+    /// The tag to use instead, if this one shouldn't be used any more
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deprecated_for: Option<String>,
+}
+
+/// A tag, with whatever the registry says about it (for JSON output)
+#[derive(Debug, Serialize)]
+pub struct RegisteredTag<'a> {
+    pub tag: String,
+    #[serde(flatten)]
+    pub info: Option<&'a TagInfo>,
+}
+
+/// Descriptions of tags, read from a `tags.toml` file
+///
+/// Each table is a tag (quoted, if heirarchical):
+///
+/// ```toml
+/// [rust]
+/// description = "The Rust programming language"
+/// owner = "chris"
+/// colour = "red"
+///
+/// ["rust/async"]
+/// description = "Futures, executors, and async/await"
+///
+/// [ml]
+/// deprecated_for = "machine-learning"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Registry {
+    pub tags: Map<String, TagInfo>,
+}
+
+impl Registry {
+    /// Load a registry file, which must exist
+    pub fn load(path: &Path) -> Result<Registry, Error> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            Error::new(e.kind(), format!("Couldn't read registry `{}`: {}", path.display(), e))
+        })?;
+        Registry::parse(&contents).map_err(|e| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid registry `{}`: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// Load `tags.toml` from a directory, if there is one
+    pub fn find(dir: &str) -> Result<Registry, Error> {
+        let path = Path::new(dir).join(REGISTRY_FILENAME);
+        if path.exists() {
+            Registry::load(&path)
+        } else {
+            Ok(Registry::default())
+        }
+    }
+
+    /// Parse a registry from a TOML string
+    pub fn parse(contents: &str) -> Result<Registry, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// Look up a tag, ignoring case and which heirarchy separator is used
+    pub fn get(&self, tag: &[String]) -> Option<&TagInfo> {
+        self.get_entry(tag).map(|(_, info)| info)
+    }
+
+    /// Look up a tag, along with its name as written in the registry
+    pub fn get_entry(&self, tag: &[String]) -> Option<(&str, &TagInfo)> {
+        let wanted = lowercase(tag);
+        self.tags
+            .iter()
+            .find(|(name, _)| lowercase(&parse_heirarchical_tag(name)) == wanted)
+            .map(|(name, info)| (name.as_str(), info))
+    }
+
+    /// Every deprecated tag, with its replacement
+    pub fn deprecated(&self) -> Vec<(Tag, String)> {
+        self.tags
+            .iter()
+            .filter_map(|(name, info)| {
+                let replacement = info.deprecated_for.as_ref()?;
+                Some((parse_heirarchical_tag(name), replacement.to_string()))
+            })
+            .collect()
+    }
+}
+
+fn lowercase(tag: &[String]) -> Vec<String> {
+    tag.iter().map(|t| t.to_lowercase()).collect()
+}
+
+/// The ANSI escape code to show text in a named colour, if it is one we know
+pub fn ansi_colour(name: &str) -> Option<&'static str> {
+    let code = match name.to_lowercase().as_str() {
+        "black" => "\x1b[30m",
+        "red" => "\x1b[31m",
+        "green" => "\x1b[32m",
+        "yellow" => "\x1b[33m",
+        "blue" => "\x1b[34m",
+        "magenta" | "purple" => "\x1b[35m",
+        "cyan" => "\x1b[36m",
+        "white" => "\x1b[37m",
+        _ => return None,
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_registry() {
+        let registry = Registry::parse(
+            r#"
+            [rust]
+            description = "The Rust programming language"
+            colour = "red"
+
+            ["rust/async"]
+            owner = "chris"
+
+            [ml]
+            deprecated_for = "machine-learning"
+            "#,
+        )
+        .unwrap();
+        let rust = registry.get(&parse_heirarchical_tag("Rust")).unwrap();
+        assert_eq!(rust.description.as_deref(), Some("The Rust programming language"));
+        assert_eq!(rust.colour.as_deref().and_then(ansi_colour), Some("\x1b[31m"));
+        let (name, info) = registry.get_entry(&parse_heirarchical_tag("rust:async")).unwrap();
+        assert_eq!((name, info.owner.as_deref()), ("rust/async", Some("chris")));
+        assert!(registry.get(&parse_heirarchical_tag("python")).is_none());
+        assert_eq!(
+            registry.deprecated(),
+            vec![(parse_heirarchical_tag("ml"), "machine-learning".to_string())]
+        );
+        assert!(Registry::parse("[rust]\nunknown = 1").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+//! Indexes for finding similar words without comparing every pair
+//!
+//! `Filter::similar_tags_in` only needs to compare tags that share a
+//! component which is *possibly* similar, so it first finds candidate pairs
+//! of words here and then checks them properly with `compare_words`.
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap as Map, BTreeSet as Set};
+use std::hash::{Hash, Hasher};
+
+use rayon::prelude::*;
+
+use crate::filter::{edit_distance, max_typo_distance, singular_forms};
+
+/// Hashes of every string made by deleting up to `max` characters from
+/// `word`
+///
+/// If two words are within `max` edits of each other (counting a swap of
+/// adjacent characters as one edit), then deleting at most `max` characters
+/// from each gives a common string. So words that share none of these can't
+/// be a typo of each other. Only hashes are kept, as there are a lot of these
+/// strings; a collision just means an extra candidate to check.
+pub(crate) fn deletion_hashes(word: &[char], max: usize) -> Vec<u64> {
+    fn recurse(word: &[char], max: usize, start: usize, skip: &mut Vec<usize>, hashes: &mut Vec<u64>) {
+        let mut hasher = DefaultHasher::new();
+        for (i, c) in word.iter().enumerate() {
+            if !skip.contains(&i) {
+                c.hash(&mut hasher);
+            }
+        }
+        hashes.push(hasher.finish());
+        if skip.len() < max {
+            for i in start..word.len() {
+                skip.push(i);
+                recurse(word, max, i + 1, skip, hashes);
+                skip.pop();
+            }
+        }
+    }
+    let mut hashes = Vec::new();
+    recurse(word, max, 0, &mut Vec::new(), &mut hashes);
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
+/// Lowercase, without `-` or `_`, so that case and separator differences
+/// land in the same bucket
+fn normalise(word: &str) -> String {
+    word.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Find every pair of words that might be similar
+///
+/// Words are bucketed by their normalised singular forms (catching case,
+/// separator, and plural differences), grouped by `synonyms`, and by the
+/// strings left after deleting a few characters (catching typos). The
+/// result maps each word to the words that *might* be similar to it, which
+/// still need checking with `compare_words`. It never misses a pair that
+/// `compare_words` would find.
+pub(crate) fn candidate_words(words: &Set<&str>, synonyms: &[Set<String>]) -> Map<String, Set<String>> {
+    let mut candidates: Map<String, Set<String>> = Map::new();
+    let mut link = |a: &str, b: &str| {
+        if a != b {
+            candidates.entry(a.to_string()).or_default().insert(b.to_string());
+            candidates.entry(b.to_string()).or_default().insert(a.to_string());
+        }
+    };
+
+    let mut buckets: Map<String, Set<&str>> = Map::new();
+    for word in words {
+        for form in singular_forms(word) {
+            buckets.entry(normalise(&form)).or_default().insert(word);
+        }
+    }
+    for bucket in buckets.values() {
+        for a in bucket {
+            for b in bucket {
+                link(a, b);
+            }
+        }
+    }
+
+    for group in synonyms {
+        let members: Vec<&&str> = words
+            .iter()
+            .filter(|word| group.contains(&word.to_lowercase()))
+            .collect();
+        for a in &members {
+            for b in &members {
+                link(a, b);
+            }
+        }
+    }
+
+    // Typos are checked on lowercase words, so index those and map back
+    let mut spellings: Map<String, Vec<&str>> = Map::new();
+    for word in words {
+        spellings.entry(word.to_lowercase()).or_default().push(word);
+    }
+    let lowers: Vec<&String> = spellings.keys().collect();
+    let mut hashes: Vec<(u64, usize)> = lowers
+        .par_iter()
+        .enumerate()
+        .flat_map_iter(|(i, lower)| {
+            let chars: Vec<char> = lower.chars().collect();
+            let hashes = deletion_hashes(&chars, max_typo_distance(chars.len()));
+            hashes.into_iter().map(move |hash| (hash, i))
+        })
+        .collect();
+    hashes.par_sort_unstable();
+    let mut typos: Set<(usize, usize)> = Set::new();
+    for same_hash in hashes.chunk_by(|a, b| a.0 == b.0) {
+        for (n, (_, a)) in same_hash.iter().enumerate() {
+            for (_, b) in &same_hash[n + 1..] {
+                typos.insert((*a, *b));
+            }
+        }
+    }
+    for (a, b) in typos {
+        let (a, b) = (lowers[a], lowers[b]);
+        let max = max_typo_distance(a.chars().count().min(b.chars().count()));
+        if edit_distance(a, b) <= max {
+            for a in &spellings[a] {
+                for b in &spellings[b] {
+                    link(a, b);
+                }
+            }
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typos_share_deletions() {
+        let hashes = |word: &str, max| deletion_hashes(&word.chars().collect::<Vec<_>>(), max);
+        assert_eq!(hashes("cat", 0).len(), 1);
+        assert_eq!(hashes("cat", 1).len(), 1 + 3);
+        assert_eq!(hashes("abcdefgh", 2).len(), 1 + 8 + 28);
+        // Deleting either 'o' from 'foo' gives the same string
+        assert_eq!(hashes("foo", 1).len(), 3);
+        let shared = |a: &str, b: &str| hashes(a, 2).iter().any(|h| hashes(b, 2).contains(h));
+        assert!(shared("python", "pyhton"));
+        assert!(shared("programming", "programing"));
+        assert!(shared("receive", "recieve"));
+        assert!(!shared("python", "java"));
+    }
+
+    #[test]
+    fn candidates_cover_all_kinds() {
+        let words: Set<&str> = ["Rust", "rust", "to-do", "todo", "cats", "cat", "ml", "machine-learning", "programming", "programing", "java"]
+            .iter()
+            .copied()
+            .collect();
+        let synonyms = vec![["ml", "machine-learning"].iter().map(|s| s.to_string()).collect()];
+        let candidates = candidate_words(&words, &synonyms);
+        let has = |a: &str, b: &str| candidates.get(a).is_some_and(|c| c.contains(b));
+        assert!(has("Rust", "rust"));
+        assert!(has("todo", "to-do"));
+        assert!(has("cats", "cat"));
+        assert!(has("ml", "machine-learning"));
+        assert!(has("programing", "programming"));
+        assert!(!candidates.contains_key("java"));
+    }
+}
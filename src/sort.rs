@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap as Map;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use rayon::prelude::*;
+
+use super::Tag;
+use crate::corpus::{Corpus, Document, Keep};
+use crate::dates::{default_date_sources, file_date, sources_need_contents, sources_need_metadata, DateSource};
+use crate::filter::Filter;
+use crate::metadata::Date;
+
+/// What to order files by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// File name, A to Z
+    Path,
+    /// Date of the file (see `file_date`), oldest first
+    Date,
+    /// Last modified time, newest first
+    Modified,
+    /// Size in bytes, largest first
+    Size,
+    /// Number of distinct tags, most first
+    Tags,
+    /// Number of distinct tags wanted by the filter, most first
+    Matching,
+    /// TF-IDF of the tags wanted by each of the filter's keywords, highest first
+    Relevance,
+}
+
+/// Names of every `SortKey`, as accepted by `from_str`
+pub const SORT_KEYS: [&str; 7] = ["path", "date", "mtime", "size", "tags", "matching", "relevance"];
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<SortKey, String> {
+        match s {
+            "path" => Ok(SortKey::Path),
+            "date" => Ok(SortKey::Date),
+            "mtime" => Ok(SortKey::Modified),
+            "size" => Ok(SortKey::Size),
+            "tags" => Ok(SortKey::Tags),
+            "matching" => Ok(SortKey::Matching),
+            "relevance" => Ok(SortKey::Relevance),
+            _ => Err(format!("Can't sort by `{}`, expected one of {}", s, SORT_KEYS.join(", "))),
+        }
+    }
+}
+
+impl SortKey {
+    /// Whether the natural order is largest (or newest) first
+    fn descending(&self) -> bool {
+        !matches!(self, SortKey::Path | SortKey::Date)
+    }
+}
+
+/// The value a file is sorted on
+#[derive(Debug, Clone, PartialEq)]
+enum SortValue {
+    Text(String),
+    Date(Date),
+    Number(f64),
+}
+
+impl SortValue {
+    fn compare(&self, other: &SortValue) -> Ordering {
+        match (self, other) {
+            (SortValue::Text(a), SortValue::Text(b)) => a.cmp(b),
+            (SortValue::Date(a), SortValue::Date(b)) => a.cmp(b),
+            (SortValue::Number(a), SortValue::Number(b)) => a.total_cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+/// How to order, and how many of, the files matching a query
+#[derive(Debug, Clone)]
+pub struct Sort {
+    pub key: SortKey,
+    /// Reverse the key's natural order
+    pub reverse: bool,
+    /// Keep at most this many files, after sorting
+    pub limit: Option<usize>,
+    /// Where to find each file's date, for `SortKey::Date`
+    pub date_sources: Vec<DateSource>,
+}
+
+impl Sort {
+    pub fn new(key: SortKey) -> Sort {
+        Sort {
+            key,
+            reverse: false,
+            limit: None,
+            date_sources: default_date_sources(),
+        }
+    }
+
+    /// Sort the files that matched `filter`
+    ///
+    /// `corpus` is every file that was searched, which is needed to know how
+    /// rare each keyword is for `SortKey::Relevance`. Files without a value
+    /// to sort on (e.g. without a date) always go last. Ties are broken by
+    /// path.
+    pub fn apply<'d>(&self, docs: Vec<&'d Document>, filter: &Filter, corpus: &Corpus) -> Vec<&'d Document> {
+        let idf = if self.key == SortKey::Relevance {
+            inverse_document_frequencies(filter, corpus)
+        } else {
+            Map::new()
+        };
+        let mut keyed: Vec<(Option<SortValue>, &Document)> = docs
+            .into_par_iter()
+            .map(|doc| (self.value(doc, filter, &idf), doc))
+            .collect();
+        let flip = self.key.descending() != self.reverse;
+        keyed.sort_by(|(a, doc_a), (b, doc_b)| {
+            let ordering = match (a, b) {
+                (Some(a), Some(b)) if flip => b.compare(a),
+                (Some(a), Some(b)) => a.compare(b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            ordering.then_with(|| doc_a.path.cmp(&doc_b.path))
+        });
+        keyed
+            .into_iter()
+            .map(|(_, doc)| doc)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// How much of each file sorting needs
+    pub fn keep(&self) -> Keep {
+        match self.key {
+            SortKey::Date if sources_need_contents(&self.date_sources) => Keep::Text,
+            SortKey::Date if sources_need_metadata(&self.date_sources) => Keep::Metadata,
+            SortKey::Relevance => Keep::Text,
+            _ => Keep::Tags,
+        }
+    }
+
+    fn value(&self, doc: &Document, filter: &Filter, idf: &Map<String, f64>) -> Option<SortValue> {
+        let number = |n: f64| Some(SortValue::Number(n));
+        match self.key {
+            SortKey::Path => Some(SortValue::Text(doc.path.to_string())),
+            SortKey::Date => file_date(&doc.path, doc.contents(), &doc.metadata, &self.date_sources).map(SortValue::Date),
+            SortKey::Modified => {
+                let modified = std::fs::metadata(&doc.path).and_then(|m| m.modified()).ok()?;
+                number(modified.duration_since(UNIX_EPOCH).ok()?.as_secs_f64())
+            }
+            SortKey::Size => number(doc.size as f64),
+            SortKey::Tags => number(doc.tags.len() as f64),
+            SortKey::Matching => number(doc.tags.iter().filter(|t| filter.wants_tag(t)).count() as f64),
+            SortKey::Relevance => {
+                let tags: Vec<Tag> = doc.occurrences().iter().map(|o| o.tag.clone()).collect();
+                number(relevance(filter, &tags, idf))
+            }
+        }
+    }
+}
+
+/// How rare each of the filter's keywords is across the corpus, as
+/// `ln(N / (1 + files wanting it)) + 1`
+fn inverse_document_frequencies(filter: &Filter, corpus: &Corpus) -> Map<String, f64> {
+    let keywords = filter.keywords();
+    let documents: Vec<Vec<bool>> = corpus
+        .documents
+        .par_iter()
+        .map(|doc| {
+            keywords
+                .iter()
+                .map(|k| doc.tags.iter().any(|t| filter.keyword_wants_tag(k, t)))
+                .collect()
+        })
+        .collect();
+    let n = documents.len() as f64;
+    keywords
+        .iter()
+        .enumerate()
+        .map(|(i, k)| {
+            let frequency = documents.iter().filter(|d| d[i]).count() as f64;
+            (k.to_string(), (n / (1.0 + frequency)).ln() + 1.0)
+        })
+        .collect()
+}
+
+/// TF-IDF score of a file, from every tag occurrence in it
+///
+/// Each keyword's term frequency is the fraction of the file's tag
+/// occurrences that it wants.
+fn relevance(filter: &Filter, occurrences: &[Tag], idf: &Map<String, f64>) -> f64 {
+    if occurrences.is_empty() {
+        return 0.0;
+    }
+    idf.iter()
+        .map(|(keyword, idf)| {
+            let count = occurrences
+                .iter()
+                .filter(|t| filter.keyword_wants_tag(keyword, t))
+                .count();
+            count as f64 / occurrences.len() as f64 * idf
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::parse_heirarchical_tag as tagparse;
+
+    #[test]
+    fn parse_keys() {
+        for name in SORT_KEYS.iter() {
+            assert!(name.parse::<SortKey>().is_ok());
+        }
+        assert!("colour".parse::<SortKey>().is_err());
+        assert!(!SortKey::Path.descending());
+        assert!(SortKey::Relevance.descending());
+    }
+
+    #[test]
+    fn relevance_prefers_rare_keywords() {
+        let keywords = ["rust", "notes"];
+        let f = Filter::new(&keywords, &[], true);
+        let idf: Map<String, f64> = [("rust".to_string(), 2.0), ("notes".to_string(), 1.0)]
+            .iter()
+            .cloned()
+            .collect();
+        let tags = |ts: &[&str]| -> Vec<Tag> { ts.iter().map(|t| tagparse(t)).collect() };
+        let rusty = relevance(&f, &tags(&["rust", "rust/async", "other"]), &idf);
+        let notey = relevance(&f, &tags(&["notes", "notes/daily", "other"]), &idf);
+        assert!(rusty > notey);
+        assert_eq!(relevance(&f, &[], &idf), 0.0);
+        assert_eq!(relevance(&f, &tags(&["other"]), &idf), 0.0);
+    }
+}
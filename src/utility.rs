@@ -1,29 +1,175 @@
 use std::collections::BTreeSet as Set;
 use std::fs::File;
-use std::io::Read;
+use std::io::{Error, ErrorKind, Read};
+use std::ops::{Deref, Range};
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
 
 use super::Tag;
-use glob::{glob, PatternError};
+use ignore::{WalkBuilder, WalkState};
+use lazy_static::lazy_static;
+use memmap2::Mmap;
 
 const HEIRARCHY_SPLITTERS: [char; 2] = [':', '/'];
 
+/// Extensions of the files that are searched for tags
+pub const EXTENSIONS: [&str; 3] = ["txt", "md", "org"];
+
+/// The file name used to mean 'read from standard input'
+pub const STDIN_FILENAME: &str = "-";
+
+lazy_static! {
+    // Stdin can only be read once, so keep its contents (or why it couldn't
+    // be read) for every later use
+    static ref STDIN_CONTENTS: Result<Vec<u8>, (ErrorKind, String)> = {
+        let mut contents = Vec::new();
+        match std::io::stdin().read_to_end(&mut contents) {
+            Ok(_) => Ok(contents),
+            Err(e) => Err((e.kind(), e.to_string())),
+        }
+    };
+}
+
+/// Everything read from standard input
+pub fn read_stdin() -> std::io::Result<&'static [u8]> {
+    match &*STDIN_CONTENTS {
+        Ok(contents) => Ok(contents),
+        Err((kind, message)) => Err(Error::new(*kind, message.to_string())),
+    }
+}
+
+/// How to walk a directory tree looking for files
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WalkOptions {
+    /// Also search hidden files and directories (starting with `.`)
+    pub hidden: bool,
+    /// Search files even if `.gitignore`, `.ignore`, or similar exclude them
+    pub no_ignore: bool,
+    /// Skip directories this returns true for, and everything in them
+    pub skip_dir: Option<fn(&Path) -> bool>,
+}
+
 /// Get all files from either a passed path or under the current directory.
 ///
-/// This will do a recursive glob for `.txt` and `.md` files. If the `root`
-/// argument is `None`, then the current directory will be used; otherwise,
-/// the given path will be used.
-pub fn get_files(root: Option<String>) -> Result<Vec<String>, PatternError> {
-    let dir = root.unwrap_or(".".to_string());
-    let mut files = Vec::new();
-    let txts = glob(&format!("{}/**/*.txt", dir))?;
-    let mds = glob(&format!("{}/**/*.md", dir))?;
-    let orgs = glob(&format!("{}/**/*.org", dir))?;
-    for filename in txts.chain(mds).chain(orgs).flatten() {
-        files.push(filename.to_string_lossy().into());
+/// This will recursively find every file with one of the `EXTENSIONS`. If
+/// the `root` argument is `None`, then the current directory will be used;
+/// otherwise, the given path will be used. Files are sorted by path.
+pub fn get_files(root: Option<String>) -> Result<Vec<String>, Error> {
+    find_files(&root.unwrap_or(".".to_string()), WalkOptions::default())
+}
+
+/// Get all files under `root` with one of the `EXTENSIONS`, sorted by path
+pub fn find_files(root: &str, options: WalkOptions) -> Result<Vec<String>, Error> {
+    if !Path::new(root).is_dir() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("`{}` isn't a directory", root),
+        ));
     }
+    let mut files: Vec<String> = walk_files(root, options).into_iter().collect();
+    files.sort();
     Ok(files)
 }
 
+/// Walk a directory tree in parallel, sending each file with one of the
+/// `EXTENSIONS` as soon as it's found
+///
+/// Every extension is looked for in the same walk. Hidden files and
+/// directories, and anything excluded by `.gitignore` or `.ignore` files,
+/// are skipped unless `options` says otherwise. Symlinks are followed, but a
+/// link back to a directory being walked is skipped rather than looped
+/// through, as are entries that can't be read, and directories
+/// `options.skip_dir` rejects. Paths start with `root`, except that a root of
+/// `.` is left off.
+pub fn walk_files(root: &str, options: WalkOptions) -> Receiver<String> {
+    let (sender, receiver) = channel();
+    let walker = WalkBuilder::new(root)
+        .hidden(!options.hidden)
+        .parents(!options.no_ignore)
+        .ignore(!options.no_ignore)
+        .git_ignore(!options.no_ignore)
+        .git_global(!options.no_ignore)
+        .git_exclude(!options.no_ignore)
+        .require_git(false)
+        .follow_links(true)
+        .filter_entry(move |entry| match options.skip_dir {
+            Some(skip) => !(entry.file_type().is_some_and(|t| t.is_dir()) && skip(entry.path())),
+            None => true,
+        })
+        .build_parallel();
+    let walking_cwd = root == ".";
+    std::thread::spawn(move || {
+        walker.run(|| {
+            let sender = sender.clone();
+            Box::new(move |entry| {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(_) => return WalkState::Continue,
+                };
+                let path = entry.path();
+                let wanted = entry.file_type().is_some_and(|t| t.is_file())
+                    && path
+                        .extension()
+                        .is_some_and(|ext| EXTENSIONS.iter().any(|e| ext == *e));
+                if !wanted {
+                    return WalkState::Continue;
+                }
+                let path = if walking_cwd { path.strip_prefix(".").unwrap_or(path) } else { path };
+                match sender.send(path.to_string_lossy().into_owned()) {
+                    Ok(()) => WalkState::Continue,
+                    // Nothing is listening any more
+                    Err(_) => WalkState::Quit,
+                }
+            })
+        })
+    });
+    receiver
+}
+
+/// A path relative to `root`, or the path itself if it isn't under `root`
+///
+/// Both are compared without any `.` components, so `./notes/a.md` is
+/// `a.md` relative to `notes`, and `notes/a.md` is `a.md` relative to
+/// `./notes/`.
+pub fn relative_path(path: &str, root: &str) -> String {
+    let normalise = |p: &str| -> PathBuf {
+        Path::new(p).components().filter(|c| *c != Component::CurDir).collect()
+    };
+    match normalise(path).strip_prefix(normalise(root)) {
+        Ok(relative) => relative.to_string_lossy().into_owned(),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// Read a list of file names, e.g. the output of `find` or `git ls-files`
+///
+/// Names are separated by newlines, unless the input contains a NUL
+/// character, in which case names are NUL-separated (as from `find -print0`).
+/// Blank entries are skipped.
+pub fn read_file_list<R: Read>(mut reader: R) -> std::io::Result<Vec<String>> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let separator = if contents.contains('\0') { '\0' } else { '\n' };
+    Ok(contents
+        .split(separator)
+        .map(|name| name.trim_end_matches('\r'))
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| name.to_string())
+        .collect())
+}
+
+/// Read the contents of a file, where `-` means standard input
+pub fn read_contents(filename: &str) -> std::io::Result<String> {
+    if filename == STDIN_FILENAME {
+        String::from_utf8(read_stdin()?.to_vec())
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "stdin isn't valid UTF-8"))
+    } else {
+        let mut contents = String::new();
+        File::open(filename)?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+}
+
 /// Get all tags for a single file
 ///
 /// This will take all 'keywords' that match from a file, where a keyword
@@ -31,46 +177,185 @@ pub fn get_files(root: Option<String>) -> Result<Vec<String>, PatternError> {
 /// or `-`. The keyword must be separate from it's surroundings (e.g. `\b`
 /// in regex terminology)...spaces, start or end of line, punctuation all
 /// count as being a 'boundary'. The leading `@` will be stripped.
-pub fn get_tags_for_file(filename: &str) -> Set<Tag> {
-    let mut file =
-        File::open(filename).unwrap_or_else(|_| panic!("Couldn't open file: `{:?}`", filename));
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)
-        .unwrap_or_else(|_| panic!("Couldn't read contents of file: `{:?}`", filename));
-    get_tags_from_string(&contents)
+///
+/// A `filename` of `-` reads from standard input. Other files are memory
+/// mapped and scanned with `get_tags_from_bytes`, so even huge files aren't
+/// read into memory as a whole.
+pub fn get_tags_for_file(filename: &str) -> std::io::Result<Set<Tag>> {
+    Ok(get_tags_from_bytes(&read_bytes(filename)?))
+}
+
+/// The raw contents of a file, memory mapped where possible
+pub enum FileBytes {
+    Mapped(Mmap),
+    Read(Vec<u8>),
+    Stdin(&'static [u8]),
+}
+
+impl Deref for FileBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileBytes::Mapped(map) => map,
+            FileBytes::Read(bytes) => bytes,
+            FileBytes::Stdin(bytes) => bytes,
+        }
+    }
+}
+
+/// Read the raw contents of a file, where `-` means standard input
+///
+/// Files are memory mapped, unless they can't be (e.g. a pipe, or empty),
+/// in which case they're read as usual.
+pub fn read_bytes(filename: &str) -> std::io::Result<FileBytes> {
+    if filename == STDIN_FILENAME {
+        return Ok(FileBytes::Stdin(read_stdin()?));
+    }
+    let mut file = File::open(filename)?;
+    if file.metadata()?.len() > 0 {
+        // Safety: the map is only ever read. If the file is changed while
+        // it's mapped, we may see some mix of the old and new contents,
+        // which is no worse than reading it while it's being written.
+        if let Ok(map) = unsafe { Mmap::map(&file) } {
+            return Ok(FileBytes::Mapped(map));
+        }
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(FileBytes::Read(bytes))
 }
 
 fn is_valid_tag_char(ch: char) -> bool {
     ch.is_alphanumeric() || "-/:_".contains(ch)
 }
 
-pub fn get_tags_from_string(contents: &str) -> Set<Tag> {
-    let mut keywords = Set::new();
-    for line in contents.lines() {
-        for word in line.split_whitespace() {
-            let word = match word.strip_prefix("\u{feff}") {
-                Some(w) => w.to_string(),
-                None => word.to_string(),
-            };
-            if !word.starts_with('@') {
-                continue;
-            }
-            let mut is_valid = true;
-            for ch in word[1..].chars() {
-                if !is_valid_tag_char(ch) {
-                    is_valid = false;
-                    break;
+/// A single tag found in some text, along with where it was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagOccurrence {
+    pub tag: Tag,
+    /// Line the tag is on, starting from 1
+    pub line: usize,
+    /// Column of the leading `@`, in characters, starting from 1
+    pub column: usize,
+    /// Bytes of the text covered by the tag, including the leading `@`
+    pub byte_range: Range<usize>,
+}
+
+/// Iterate over every tag in some text, in the order they appear
+///
+/// Tags are found using the same rules as `get_tags_for_file`. Unlike
+/// `get_tags_from_string`, repeated tags are all returned.
+pub fn tag_occurrences(contents: &str) -> impl Iterator<Item = TagOccurrence> + '_ {
+    let mut line_start = 0;
+    contents
+        .split_inclusive('\n')
+        .enumerate()
+        .flat_map(move |(i, line)| {
+            let start = line_start;
+            line_start += line.len();
+            // Track the column as we go, rather than re-counting from the
+            // start of the line for every tag
+            let (mut last_offset, mut last_column) = (0, 1);
+            line.split_whitespace().filter_map(move |word| {
+                let mut offset = word.as_ptr() as usize - line.as_ptr() as usize;
+                let word = match word.strip_prefix('\u{feff}') {
+                    Some(w) => {
+                        offset += '\u{feff}'.len_utf8();
+                        w
+                    }
+                    None => word,
+                };
+                let tag = word.strip_prefix('@')?;
+                if tag.is_empty() || !tag.chars().all(is_valid_tag_char) {
+                    return None;
                 }
+                last_column += line[last_offset..offset].chars().count();
+                last_offset = offset;
+                Some(TagOccurrence {
+                    tag: parse_heirarchical_tag(tag),
+                    line: i + 1,
+                    column: last_column,
+                    byte_range: start + offset..start + offset + word.len(),
+                })
+            })
+        })
+}
+
+pub fn get_tags_from_string(contents: &str) -> Set<Tag> {
+    tag_occurrences(contents).map(|occurrence| occurrence.tag).collect()
+}
+
+/// The byte order mark, which is ignored at the start of a word
+const BOM: &[u8] = "\u{feff}".as_bytes();
+
+/// The length of the character starting at `bytes[i]`, and whether it's
+/// whitespace (as `str::split_whitespace` uses). Bytes that aren't valid
+/// UTF-8 are taken one at a time, and aren't whitespace.
+fn char_at(bytes: &[u8], i: usize) -> (usize, bool) {
+    let first = bytes[i];
+    if first.is_ascii() {
+        return (1, (first as char).is_whitespace());
+    }
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return (1, false),
+    };
+    match bytes.get(i..i + len).and_then(|b| std::str::from_utf8(b).ok()) {
+        Some(c) => (len, c.chars().all(char::is_whitespace)),
+        None => (1, false),
+    }
+}
+
+/// Get all tags from raw bytes, e.g. a memory-mapped file
+///
+/// This finds the same tags as `get_tags_from_string`, but scans the bytes
+/// directly rather than needing the whole text as a `String`, and only
+/// allocates once for each distinct tag. Words that aren't valid UTF-8 are
+/// never tags.
+pub fn get_tags_from_bytes(bytes: &[u8]) -> Set<Tag> {
+    let mut found: Set<&str> = Set::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let (len, is_space) = char_at(bytes, i);
+        if is_space {
+            i += len;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() {
+            let (len, is_space) = char_at(bytes, i);
+            if is_space {
+                break;
             }
-            if is_valid && !word[1..].is_empty() {
-                keywords.insert(parse_heirarchical_tag(&word[1..]));
-            }
+            i += len;
+        }
+        let word = &bytes[start..i];
+        let word = word.strip_prefix(BOM).unwrap_or(word);
+        let tag = match word.strip_prefix(b"@").map(std::str::from_utf8) {
+            Some(Ok(tag)) => tag,
+            _ => continue,
+        };
+        if !tag.is_empty() && tag.chars().all(is_valid_tag_char) {
+            found.insert(tag);
         }
     }
-    keywords
+    found.into_iter().map(parse_heirarchical_tag).collect()
 }
 
 pub fn display_as_tree(heirarchy: &[Tag]) -> String {
+    display_as_annotated_tree(heirarchy, |_| None)
+}
+
+/// Display tags as a tree, with a note after any tag that `annotate` gives one for
+///
+/// `annotate` is given the full path to each node of the tree.
+pub fn display_as_annotated_tree<F>(heirarchy: &[Tag], annotate: F) -> String
+where
+    F: Fn(&[String]) -> Option<String>,
+{
     let mut heirarchy: Vec<Tag> = heirarchy.to_vec();
     heirarchy.sort();
     let mut path: Vec<String> = vec![];
@@ -92,12 +377,27 @@ pub fn display_as_tree(heirarchy: &[Tag]) -> String {
                 path.push(tag.to_string());
             }
             let indents = "    ".repeat(path.len() - 1);
-            output.push_str(&format!("{}{}\n", indents, path[path.len() - 1]));
+            output.push_str(&format!("{}{}", indents, path[path.len() - 1]));
+            if let Some(note) = annotate(&path) {
+                output.push_str(&format!("  {}", note));
+            }
+            output.push('\n');
         }
     }
     output
 }
 
+/// Check if `tag` is `parent`, or anywhere beneath it in the heirarchy
+///
+/// Components are compared ignoring case, so `Rust/async` is within `rust`.
+pub fn tag_is_within(tag: &[String], parent: &[String]) -> bool {
+    tag.len() >= parent.len()
+        && tag
+            .iter()
+            .zip(parent)
+            .all(|(a, b)| a.to_lowercase() == b.to_lowercase())
+}
+
 pub fn parse_heirarchical_tag(s: &str) -> Vec<String> {
     s.trim_start_matches('@')
         .split(|c: char| HEIRARCHY_SPLITTERS.contains(&c))
@@ -106,10 +406,47 @@ pub fn parse_heirarchical_tag(s: &str) -> Vec<String> {
 }
 
 #[allow(unused_imports)]
+#[cfg(test)]
+// Some tests build their expected values from nested `vec!`s
+#[allow(clippy::useless_vec, clippy::redundant_iter_cloned)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use std::collections::BTreeSet as Set;
 
+    proptest! {
+        #[test]
+        fn tags_from_bytes_match_string(
+            // Mostly tag characters, with the whitespace and other characters
+            // that end or spoil a tag
+            contents in "([@a-zA-Zé0-9/:_-]|[ \t\n\r.,#!]|\u{a0}|\u{3000}|\u{feff}|\u{85}){0,80}"
+        ) {
+            prop_assert_eq!(get_tags_from_bytes(contents.as_bytes()), get_tags_from_string(&contents));
+        }
+
+        #[test]
+        fn tags_from_any_bytes_match_string(contents in any::<String>()) {
+            prop_assert_eq!(get_tags_from_bytes(contents.as_bytes()), get_tags_from_string(&contents));
+        }
+    }
+
+    #[test]
+    fn tags_from_mapped_file() {
+        let path = std::env::temp_dir().join(format!("tagsearch-mapped-{}.md", std::process::id()));
+        let path_str = path.to_string_lossy().into_owned();
+        let contents = "\u{feff}@a text\n@b/c\u{a0}@d @a x@y\n\u{3000}@é:f";
+        std::fs::write(&path, contents).unwrap();
+        assert_eq!(get_tags_for_file(&path_str).unwrap(), get_tags_from_string(contents));
+        std::fs::write(&path, "").unwrap();
+        assert!(get_tags_for_file(&path_str).unwrap().is_empty());
+        // Invalid UTF-8 only stops the word it's in from being a tag
+        std::fs::write(&path, b"@a \xff@b @c\xff @d").unwrap();
+        let tags: Vec<String> = get_tags_for_file(&path_str).unwrap().iter().map(|t| t.join("/")).collect();
+        assert_eq!(tags, vec!["a", "d"]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(get_tags_for_file(&path_str).is_err());
+    }
+
     #[test]
     fn test_tags_from_string() {
         let output = vec![vec!["a"], vec!["b"], vec!["c"], vec!["d", "e", "f"]]
@@ -121,6 +458,101 @@ mod tests {
         assert_eq!(get_tags_from_string(input), output);
     }
 
+    #[test]
+    fn test_tag_occurrences() {
+        let input = "@a text\n\u{feff}@b/c — @a x@y @\n  @d";
+        let found: Vec<(String, usize, usize, &str)> = tag_occurrences(input)
+            .map(|o| (o.tag.join("/"), o.line, o.column, &input[o.byte_range]))
+            .collect();
+        let expected = vec![
+            ("a".to_string(), 1, 1, "@a"),
+            ("b/c".to_string(), 2, 2, "@b/c"),
+            ("a".to_string(), 2, 9, "@a"),
+            ("d".to_string(), 3, 3, "@d"),
+        ];
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn walk_skips_hidden_ignored_and_loops() {
+        let root = std::env::temp_dir().join(format!("tagsearch-walk-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for dir in ["notes/deep", ".hidden", "build", "view/tag"].iter() {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["a.md", "notes/b.txt", "notes/deep/c.org", "notes/d.rs", ".hidden/e.md", "build/f.md", "view/tag/g.md"].iter() {
+            std::fs::write(root.join(file), "@tag").unwrap();
+        }
+        std::fs::write(root.join("view").join(crate::materialize::MARKER), "").unwrap();
+        std::fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        // A link back up the tree must not be walked forever
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("notes/deep/loop")).unwrap();
+
+        let root_str = root.to_string_lossy().into_owned();
+        let relative = |options| -> Vec<String> {
+            find_files(&root_str, options)
+                .unwrap()
+                .iter()
+                .map(|f| Path::new(f).strip_prefix(&root).unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+        let skip_views = WalkOptions {
+            skip_dir: Some(crate::materialize::is_view),
+            ..WalkOptions::default()
+        };
+        assert_eq!(relative(skip_views), vec!["a.md", "notes/b.txt", "notes/deep/c.org"]);
+        assert_eq!(
+            relative(WalkOptions::default()),
+            vec!["a.md", "notes/b.txt", "notes/deep/c.org", "view/tag/g.md"]
+        );
+        let everything = WalkOptions {
+            hidden: true,
+            no_ignore: true,
+            ..skip_views
+        };
+        assert_eq!(
+            relative(everything),
+            vec![".hidden/e.md", "a.md", "build/f.md", "notes/b.txt", "notes/deep/c.org"]
+        );
+        assert!(find_files(&root.join("missing").to_string_lossy(), WalkOptions::default()).is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn walked_paths_start_with_the_root() {
+        // Relative to the package, where tests are run from
+        let root = format!("target/tagsearch-walk-root-{}", std::process::id());
+        std::fs::create_dir_all(format!("{}/sub", root)).unwrap();
+        std::fs::write(format!("{}/sub/a.md", root), "@tag").unwrap();
+        let dotted = format!("./{}", root);
+        assert_eq!(find_files(&dotted, WalkOptions::default()).unwrap(), vec![format!("{}/sub/a.md", dotted)]);
+        assert_eq!(find_files(&root, WalkOptions::default()).unwrap(), vec![format!("{}/sub/a.md", root)]);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(relative_path("./notes/a.md", "notes"), "a.md");
+        assert_eq!(relative_path("notes/sub/a.md", "./notes/"), "sub/a.md");
+        assert_eq!(relative_path("/abs/notes/a.md", "/abs/notes"), "a.md");
+        assert_eq!(relative_path("other/a.md", "notes"), "other/a.md");
+    }
+
+    #[test]
+    fn test_read_file_list() {
+        let newlines = "a.md\nsub dir/b.txt\r\n\nc.org\n";
+        assert_eq!(
+            read_file_list(newlines.as_bytes()).unwrap(),
+            vec!["a.md", "sub dir/b.txt", "c.org"]
+        );
+        let nuls = "a.md\0with\nnewline.txt\0";
+        assert_eq!(
+            read_file_list(nuls.as_bytes()).unwrap(),
+            vec!["a.md", "with\nnewline.txt"]
+        );
+    }
+
     #[test]
     fn display_as_tree_test() {
         let output2 = String::from("completely\n    unrelated\n        heirarchy\nphilosophy\n    mindset\n    stoicism\n        quote\n");